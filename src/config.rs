@@ -0,0 +1,166 @@
+// src/config.rs
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::validate::{toml_positions, yaml_positions};
+
+/// Config file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Format from the file extension (`.yaml`, `.yml`, `.toml`, `.json`).
+    pub fn from_path(path: &str) -> Result<Self> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match ext.as_deref() {
+            Some("yaml" | "yml") => Ok(ConfigFormat::Yaml),
+            Some("toml") => Ok(ConfigFormat::Toml),
+            Some("json") => Ok(ConfigFormat::Json),
+            _ => bail!(
+                "cannot tell the format of {} from its extension; \
+                 use .yaml, .yml, .toml or .json, or pass --format",
+                path
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigFormat::Yaml => "YAML",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Json => "JSON",
+        })
+    }
+}
+
+/// Raw config text together with where it came from.
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    /// File path, or a description such as `command line`.
+    pub origin: String,
+    pub text: String,
+    pub format: ConfigFormat,
+}
+
+impl ConfigSource {
+    pub fn new(origin: impl Into<String>, text: impl Into<String>, format: ConfigFormat) -> Self {
+        Self {
+            origin: origin.into(),
+            text: text.into(),
+            format,
+        }
+    }
+
+    /// Read config file from disk. Without an explicit `format`,
+    /// it is picked from the file extension.
+    pub fn read(path: &str, format: Option<ConfigFormat>) -> Result<Self> {
+        let format = match format {
+            Some(format) => format,
+            None => ConfigFormat::from_path(path)?,
+        };
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+        Ok(Self::new(path, text, format))
+    }
+
+    /// Parse config into type `T`.
+    ///
+    /// Errors look the same for every format:
+    /// `failed to parse <origin> as <FORMAT>: <line>:<column>: <message>`.
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        let (message, location) = match self.format {
+            ConfigFormat::Yaml => match serde_yaml::from_str(&self.text) {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let location = err.location().map(|l| (l.line(), l.column()));
+                    (strip_location(&err.to_string()), location)
+                }
+            },
+            ConfigFormat::Json => match serde_json::from_str(&self.text) {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let location = (err.line() > 0).then(|| (err.line(), err.column()));
+                    (strip_location(&err.to_string()), location)
+                }
+            },
+            ConfigFormat::Toml => match toml::from_str(&self.text) {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let location = err.span().map(|span| line_column(&self.text, span.start));
+                    (err.message().trim().to_string(), location)
+                }
+            },
+        };
+
+        Err(match location {
+            Some((line, column)) => anyhow!(
+                "failed to parse {} as {}: {}:{}: {}",
+                self.origin,
+                self.format,
+                line,
+                column,
+                message
+            ),
+            None => anyhow!(
+                "failed to parse {} as {}: {}",
+                self.origin,
+                self.format,
+                message
+            ),
+        })
+    }
+
+    /// Value of the top-level `kind` key (e.g. `tcp_echo`), if any.
+    pub fn kind(&self) -> Result<Option<String>> {
+        #[derive(Deserialize)]
+        struct KindProbe {
+            kind: Option<String>,
+        }
+
+        Ok(self.parse::<KindProbe>()?.kind)
+    }
+
+    /// Map every key path (e.g. `routes[1].handler`) to its 1-based `(line, column)`.
+    pub fn positions(&self) -> HashMap<String, (usize, usize)> {
+        match self.format {
+            // JSON is valid YAML, so the YAML locator covers it too.
+            ConfigFormat::Yaml | ConfigFormat::Json => yaml_positions(&self.text),
+            ConfigFormat::Toml => toml_positions(&self.text),
+        }
+    }
+}
+
+/// 1-based line and column of byte offset `offset` in `text`.
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// serde_yaml and serde_json append ` at line L column C` to their messages;
+/// the location is reported separately.
+fn strip_location(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(idx) if message[idx..].contains(" column ") => message[..idx].to_string(),
+        _ => message.to_string(),
+    }
+}
@@ -0,0 +1,300 @@
+// src/connection.rs
+use std::borrow::Cow;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::read_mode::ByteString;
+use crate::validate::Issue;
+
+/// Partials shared by the TCP templates: those of the read modes, of the
+/// connection handling (`tcp/`) and the shutdown signal.
+pub const TCP_PARTIALS: &[&str] = &[
+    "read_mode/codec.rs.hbs",
+    "read_mode/delimiter.rs.hbs",
+    "read_mode/length_field.rs.hbs",
+    "read_mode/length_header.rs.hbs",
+    "read_mode/line.rs.hbs",
+    "read_mode/oversize.rs.hbs",
+    "read_mode/response.rs.hbs",
+    "read_mode/varint.rs.hbs",
+    "shutdown/signal.rs.hbs",
+    "tcp/accept.rs.hbs",
+    "tcp/limits.rs.hbs",
+    "tcp/reply.rs.hbs",
+    "tcp/stopping.rs.hbs",
+    "tcp/timeouts.rs.hbs",
+];
+
+/// Default `shutdown_grace` of the generated servers.
+pub fn default_shutdown_grace() -> ConfigDuration {
+    ConfigDuration(Duration::from_secs(10))
+}
+
+/// Time limits of the connections of the generated TCP servers; unset ones
+/// don't apply.
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct YamlTimeouts {
+    /// Close connections that send nothing for this long between frames.
+    pub idle: Option<ConfigDuration>,
+    /// Close connections that take longer to send a whole frame once it started.
+    pub read_frame: Option<ConfigDuration>,
+    /// Close connections that take longer to accept a reply.
+    pub write: Option<ConfigDuration>,
+}
+
+impl YamlTimeouts {
+    /// Semantic checks; `path` is the key path of the timeouts in the config.
+    pub fn validate(&self, path: &str) -> Vec<Issue> {
+        [
+            ("idle", self.idle, "30s"),
+            ("read_frame", self.read_frame, "5s"),
+            ("write", self.write, "5s"),
+        ]
+        .into_iter()
+        .filter(|(_, timeout, _)| timeout.is_some_and(|t| t.0.is_zero()))
+        .map(|(key, _, example)| {
+            Issue::new(format!("{}.{}", path, key), "must be longer than 0")
+                .suggest(format!("{}: {}", key, example))
+        })
+        .collect()
+    }
+}
+
+/// [`YamlTimeouts`] as used by the templates: Rust expressions of the set
+/// timeouts.
+#[derive(Debug, Default, Serialize)]
+pub struct TimeoutsTemplateCtx {
+    pub idle: Option<String>,
+    pub read_frame: Option<String>,
+    pub write: Option<String>,
+    /// Any timeout is set.
+    pub any: bool,
+}
+
+impl From<YamlTimeouts> for TimeoutsTemplateCtx {
+    fn from(timeouts: YamlTimeouts) -> Self {
+        TimeoutsTemplateCtx {
+            any: timeouts.idle.is_some()
+                || timeouts.read_frame.is_some()
+                || timeouts.write.is_some(),
+            idle: timeouts.idle.map(ConfigDuration::rust_expr),
+            read_frame: timeouts.read_frame.map(ConfigDuration::rust_expr),
+            write: timeouts.write.map(ConfigDuration::rust_expr),
+        }
+    }
+}
+
+/// Caps on the connections open at once; unset ones don't apply.
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct YamlConnectionLimits {
+    /// Connections open at once over all clients.
+    pub max_connections: Option<usize>,
+    /// Connections open at once from a single client IP address.
+    pub max_connections_per_ip: Option<usize>,
+    /// What happens to connections over a limit: `reject` (default) or `wait`.
+    #[serde(default)]
+    pub on_limit: OnLimit,
+    /// Sent to rejected connections before closing them, written like `delim`
+    /// and framed like replies.
+    pub goodbye_frame: Option<ByteString>,
+}
+
+/// What happens to connections over `max_connections` or
+/// `max_connections_per_ip`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnLimit {
+    /// Close them, after sending the `goodbye_frame` if there is one.
+    #[default]
+    Reject,
+    /// Hold them until another connection closes: new connections stay in the
+    /// listen backlog, those over the per-IP limit are accepted but not served.
+    Wait,
+}
+
+impl YamlConnectionLimits {
+    /// Semantic checks; the limits are top-level keys of the config.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues: Vec<Issue> = [
+            ("max_connections", self.max_connections, "1000"),
+            ("max_connections_per_ip", self.max_connections_per_ip, "16"),
+        ]
+        .into_iter()
+        .filter(|(_, max, _)| *max == Some(0))
+        .map(|(key, _, example)| {
+            Issue::new(key, "must be at least 1").suggest(format!("{}: {}", key, example))
+        })
+        .collect();
+
+        let limited = self.max_connections.is_some() || self.max_connections_per_ip.is_some();
+        if !limited && (self.on_limit != OnLimit::default() || self.goodbye_frame.is_some()) {
+            let key = if self.goodbye_frame.is_some() {
+                "goodbye_frame"
+            } else {
+                "on_limit"
+            };
+            issues.push(
+                Issue::new(
+                    key,
+                    "has no effect without max_connections or max_connections_per_ip",
+                )
+                .suggest("max_connections: 1000"),
+            );
+        }
+        if self.on_limit == OnLimit::Wait && self.goodbye_frame.is_some() {
+            issues.push(
+                Issue::new(
+                    "goodbye_frame",
+                    "is only sent to rejected connections, but on_limit is `wait`",
+                )
+                .suggest("on_limit: reject"),
+            );
+        }
+        issues
+    }
+}
+
+/// [`YamlConnectionLimits`] as used by the templates.
+#[derive(Debug, Default, Serialize)]
+pub struct LimitsTemplateCtx {
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    /// `on_limit: wait`.
+    pub wait: bool,
+    pub goodbye_frame_literal: Option<String>,
+    /// Any limit is set.
+    pub any: bool,
+}
+
+impl From<YamlConnectionLimits> for LimitsTemplateCtx {
+    fn from(limits: YamlConnectionLimits) -> Self {
+        LimitsTemplateCtx {
+            any: limits.max_connections.is_some() || limits.max_connections_per_ip.is_some(),
+            max_connections: limits.max_connections,
+            max_connections_per_ip: limits.max_connections_per_ip,
+            wait: limits.on_limit == OnLimit::Wait,
+            goodbye_frame_literal: limits.goodbye_frame.map(|frame| frame.rust_literal()),
+        }
+    }
+}
+
+/// A duration in a config: a number of seconds, or a number with one of the
+/// units `ms`, `s`, `m` and `h` (`500ms`, `30s`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigDuration(pub Duration);
+
+impl ConfigDuration {
+    /// Parse a duration written as text, e.g. `30s`; a bare number is seconds.
+    pub fn parse(text: &str) -> Result<Self> {
+        let unit_start = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (digits, unit) = text.split_at(unit_start);
+        if digits.is_empty() {
+            bail!(
+                "invalid duration `{}`: expected a number of seconds or a number with ms, s, m or h",
+                text
+            );
+        }
+        let Ok(value) = digits.parse::<u64>() else {
+            bail!("duration `{}` is too long", text);
+        };
+
+        let millis_per_unit = match unit {
+            "ms" => 1,
+            "" | "s" => 1_000,
+            "m" => 60_000,
+            "h" => 3_600_000,
+            _ => bail!(
+                "unknown unit `{}` in duration `{}` (expected ms, s, m or h)",
+                unit,
+                text
+            ),
+        };
+        match value.checked_mul(millis_per_unit) {
+            Some(millis) => Ok(ConfigDuration(Duration::from_millis(millis))),
+            None => bail!("duration `{}` is too long", text),
+        }
+    }
+
+    /// Rust expression of the duration, e.g. `std::time::Duration::from_secs(30)`.
+    pub fn rust_expr(self) -> String {
+        let millis = self.0.as_millis();
+        if millis.is_multiple_of(1_000) {
+            format!("std::time::Duration::from_secs({})", millis / 1_000)
+        } else {
+            format!("std::time::Duration::from_millis({})", millis)
+        }
+    }
+}
+
+/// Largest unit that divides the duration: `90s`, `2m`, `1500ms`.
+impl fmt::Display for ConfigDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.as_millis();
+        match millis {
+            0 => f.write_str("0s"),
+            _ if millis.is_multiple_of(3_600_000) => write!(f, "{}h", millis / 3_600_000),
+            _ if millis.is_multiple_of(60_000) => write!(f, "{}m", millis / 60_000),
+            _ if millis.is_multiple_of(1_000) => write!(f, "{}s", millis / 1_000),
+            _ => write!(f, "{}ms", millis),
+        }
+    }
+}
+
+impl Serialize for ConfigDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ConfigDurationVisitor;
+
+        impl<'de> Visitor<'de> for ConfigDurationVisitor {
+            type Value = ConfigDuration;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a number of seconds or a duration such as `500ms`, `30s` or `5m`")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<ConfigDuration, E> {
+                Ok(ConfigDuration(Duration::from_secs(value)))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<ConfigDuration, E> {
+                u64::try_from(value)
+                    .map(|secs| ConfigDuration(Duration::from_secs(secs)))
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<ConfigDuration, E> {
+                ConfigDuration::parse(value).map_err(|err| E::custom(err))
+            }
+        }
+
+        deserializer.deserialize_any(ConfigDurationVisitor)
+    }
+}
+
+impl JsonSchema for ConfigDuration {
+    fn schema_name() -> Cow<'static, str> {
+        "ConfigDuration".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A number of seconds, or a number with a unit: `500ms`, `30s`, `5m`, `1h`.",
+            "oneOf": [
+                { "type": "integer", "minimum": 0 },
+                { "type": "string", "pattern": "^[0-9]+(ms|s|m|h)?$" }
+            ]
+        })
+    }
+}
@@ -0,0 +1,567 @@
+// src/generator.rs
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use handlebars::Handlebars;
+use schemars::{schema_for, JsonSchema, Schema};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::config::{ConfigFormat, ConfigSource};
+use crate::http_axum::HttpAxumGenerator;
+use crate::lock::{checksum, write_lock, Lock, TemplatesLock};
+use crate::migrate::{self, CURRENT_VERSION};
+use crate::out_dir::{foreign_entry, prepare_out_dir, OverwritePolicy};
+use crate::post_generate::{
+    check_project, file_origins, format_files, with_config, PostStep, PostSteps,
+};
+use crate::preview::{plan_changes, print_diff, print_dry_run};
+use crate::tcp_echo::TcpEchoGenerator;
+use crate::tcp_worker::TcpWorkerGenerator;
+use crate::templates::{partial_name, report_overrides, TemplateSet};
+use crate::util::{resolve_out_dir, template_set};
+use crate::validate::{check, Issue, ValidationError};
+
+/// Options shared by every generator subcommand.
+#[derive(Debug, Clone, Default, Args)]
+pub struct GenerateArgs {
+    /// Override output directory.
+    #[arg(long)]
+    pub out_dir: Option<String>,
+
+    /// Directory with template overrides (falls back to embedded templates).
+    #[arg(long)]
+    pub templates: Option<String>,
+
+    /// List the files that would be created or changed, without writing anything.
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Print a unified diff against the files on disk, without writing anything.
+    #[arg(long, default_value_t = false)]
+    pub diff: bool,
+
+    /// Write into the output directory even if it holds files not generated by netgen.
+    #[arg(long, default_value_t = false, conflicts_with = "backup")]
+    pub force: bool,
+
+    /// Move an output directory with files not generated by netgen aside before generating.
+    #[arg(long, default_value_t = false)]
+    pub backup: bool,
+
+    /// Format the generated Rust files with rustfmt.
+    #[arg(long, default_value_t = false)]
+    pub fmt: bool,
+
+    /// Run `cargo check` on the generated project.
+    #[arg(long, default_value_t = false)]
+    pub check: bool,
+}
+
+impl GenerateArgs {
+    /// Whether only a preview (`--dry-run` or `--diff`) was asked for.
+    pub fn preview(&self) -> bool {
+        self.dry_run || self.diff
+    }
+
+    pub fn overwrite_policy(&self) -> OverwritePolicy {
+        if self.force {
+            OverwritePolicy::Force
+        } else if self.backup {
+            OverwritePolicy::Backup
+        } else {
+            OverwritePolicy::Refuse
+        }
+    }
+}
+
+/// `netgen generate`: pick the generator from the config's `kind` key.
+#[derive(Args, Debug)]
+pub struct GenerateCmd {
+    /// Path to config file with a top-level `kind` key (YAML, TOML or JSON).
+    #[arg(long)]
+    pub config: String,
+
+    /// Config format (detected from the file extension by default).
+    #[arg(long, value_enum)]
+    pub format: Option<ConfigFormat>,
+
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+}
+
+/// One file of a generated project.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputFile {
+    /// Template path relative to `templates/` (e.g. `tcp_echo/main.rs.hbs`).
+    pub template: &'static str,
+    /// Output path relative to the project root (e.g. `src/main.rs`).
+    pub path: &'static str,
+}
+
+/// Settings every config carries, regardless of the generator.
+#[derive(Debug, Clone)]
+pub struct ProjectSettings {
+    pub project_name: String,
+    pub out_dir: Option<String>,
+    pub templates_dir: Option<String>,
+    pub post_generate: Vec<PostStep>,
+}
+
+/// In-memory project: file contents keyed by path relative to the project
+/// root, with `/` as separator (e.g. `src/main.rs`).
+pub type FileTree = BTreeMap<String, String>;
+
+/// Rendered project, not yet written to disk.
+#[derive(Debug, Default)]
+pub struct Rendered {
+    pub files: FileTree,
+    /// Template overrides that were used.
+    pub overrides: Vec<PathBuf>,
+    /// Messages produced while merging with existing files.
+    pub notes: Vec<String>,
+    /// Hash of the template context, i.e. of the fully resolved config.
+    pub config_hash: String,
+    /// Templates the files were rendered from.
+    pub templates: TemplatesLock,
+}
+
+/// A code generator for one kind of network service.
+///
+/// Implementors only describe their config, template context and output files;
+/// rendering and writing are shared.
+pub trait Generator {
+    /// Config as read from YAML.
+    type Config: DeserializeOwned + JsonSchema;
+    /// Context passed to the templates.
+    type Ctx: Serialize;
+
+    /// Name used on the command line and in the registry (e.g. `tcp-echo`).
+    fn name(&self) -> &'static str;
+
+    /// Short human-readable description (e.g. `TCP echo server`).
+    fn description(&self) -> &'static str;
+
+    /// Value of the config's `kind` key that selects this generator (e.g. `tcp_echo`).
+    fn kind(&self) -> String {
+        self.name().replace('-', "_")
+    }
+
+    /// Files produced by this generator.
+    fn files(&self) -> &'static [OutputFile];
+
+    /// Templates shared between generators that [`Generator::files`] include
+    /// as partials, relative to `templates/` (see [`partial_name`]).
+    fn partials(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Project name, output and templates directory from the config.
+    fn settings(&self, cfg: &Self::Config) -> ProjectSettings;
+
+    /// Build the template context from the config.
+    fn context(&self, cfg: Self::Config) -> Self::Ctx;
+
+    /// JSON Schema of the config, including the optional `kind` and `version` keys.
+    fn config_schema(&self) -> Schema {
+        with_kind_and_version(schema_for!(Self::Config), &self.kind())
+    }
+
+    /// Semantic checks on the config, run before anything is written.
+    fn validate(&self, _cfg: &Self::Config) -> Vec<Issue> {
+        Vec::new()
+    }
+
+    /// Render all output files in memory.
+    fn render(&self, ctx: &Self::Ctx, templates: &TemplateSet) -> Result<Rendered> {
+        let mut hbs = Handlebars::new();
+        // Disable HTML escaping since we're generating code, not HTML
+        hbs.register_escape_fn(handlebars::no_escape);
+
+        let mut rendered = Rendered {
+            config_hash: checksum(serde_json::to_string(ctx)?),
+            ..Default::default()
+        };
+        rendered.templates.overrides_dir = templates
+            .overrides_dir()
+            .map(|dir| dir.to_string_lossy().into_owned());
+        for partial in self.partials() {
+            let registered = templates.register(&mut hbs, partial_name(partial), partial)?;
+            rendered.overrides.extend(registered.override_path);
+            rendered
+                .templates
+                .checksums
+                .insert(partial.to_string(), registered.checksum);
+        }
+        for file in self.files() {
+            let registered = templates.register(&mut hbs, file.path, file.template)?;
+            rendered.overrides.extend(registered.override_path);
+            rendered
+                .templates
+                .checksums
+                .insert(file.template.to_string(), registered.checksum);
+            let contents = hbs.render(file.path, ctx)?;
+            rendered.files.insert(file.path.to_string(), contents);
+        }
+
+        Ok(rendered)
+    }
+
+    /// Combine a rendered file with the version already on disk.
+    /// By default the rendered file replaces it.
+    fn merge_existing(
+        &self,
+        _path: &Path,
+        rendered: String,
+        _existing: &str,
+        _notes: &mut Vec<String>,
+    ) -> String {
+        rendered
+    }
+
+    /// Apply [`Generator::merge_existing`] to every rendered file that already exists in `out_dir`.
+    fn merge_with_disk(&self, out_dir: &Path, rendered: &mut Rendered) -> Result<()> {
+        for (path, contents) in &mut rendered.files {
+            let on_disk = out_dir.join(path);
+            if !on_disk.is_file() {
+                continue;
+            }
+
+            let existing = std::fs::read_to_string(&on_disk)?;
+            *contents = self.merge_existing(
+                Path::new(path),
+                std::mem::take(contents),
+                &existing,
+                &mut rendered.notes,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Write rendered files and `.netgen/lock.json` into `out_dir`.
+    fn write(&self, out_dir: &Path, rendered: &Rendered) -> Result<()> {
+        write_tree(out_dir, &rendered.files)?;
+        let lock = Lock::new(
+            Generator::name(self),
+            rendered.config_hash.clone(),
+            rendered.templates.clone(),
+            &rendered.files,
+        );
+        write_lock(out_dir, &lock)
+    }
+
+    /// Render the project and write it into `out_dir`.
+    /// Returns the template overrides that were used.
+    fn generate(
+        &self,
+        ctx: &Self::Ctx,
+        out_dir: &Path,
+        templates: &TemplateSet,
+    ) -> Result<Vec<PathBuf>> {
+        let mut rendered = self.render(ctx, templates)?;
+        self.merge_with_disk(out_dir, &mut rendered)?;
+        self.write(out_dir, &rendered)?;
+        Ok(rendered.overrides)
+    }
+
+    /// Generate a project from a parsed config, resolving the output and
+    /// templates directories against `args`. Returns the output directory.
+    ///
+    /// `cfg` is not validated again: [`DynGenerator::run`] loads configs with
+    /// [`Generator::validate`] and reports issues with their location.
+    fn run(&self, cfg: Self::Config, args: &GenerateArgs) -> Result<PathBuf> {
+        let settings = self.settings(&cfg);
+        let steps = PostSteps::new(args, &settings.post_generate);
+        let out_dir = self.run_with(cfg, args, &mut |_| Ok(()))?;
+
+        if steps.check && !args.preview() {
+            let templates = template_set(args.templates.clone().or(settings.templates_dir));
+            check_project(&out_dir, &file_origins(self.files(), &templates, ""))?;
+        }
+
+        Ok(out_dir)
+    }
+
+    /// Same as [`Generator::run`], but `edit` may change the rendered files
+    /// before they are previewed or written. Formats the files if asked to,
+    /// but never runs `cargo check`: that is up to the caller.
+    fn run_with(
+        &self,
+        cfg: Self::Config,
+        args: &GenerateArgs,
+        edit: &mut dyn FnMut(&mut Rendered) -> Result<()>,
+    ) -> Result<PathBuf> {
+        let settings = self.settings(&cfg);
+        let steps = PostSteps::new(args, &settings.post_generate);
+        let templates = template_set(args.templates.clone().or(settings.templates_dir));
+        let out_dir = PathBuf::from(resolve_out_dir(
+            args.out_dir.clone(),
+            settings.out_dir,
+            &settings.project_name,
+        ));
+
+        let preview = args.preview();
+        let foreign = foreign_entry(&out_dir)?;
+        if let (true, Some(entry)) = (preview, &foreign) {
+            println!(
+                "⚠️  {} contains files not generated by netgen (e.g. {}); \
+                 generating would need --force or --backup",
+                out_dir.to_string_lossy(),
+                entry.to_string_lossy()
+            );
+        }
+
+        let ctx = self.context(cfg);
+        let mut rendered = self.render(&ctx, &templates)?;
+        // Foreign files are only merged with when writing into them anyway.
+        if foreign.is_none() || args.overwrite_policy() == OverwritePolicy::Force {
+            self.merge_with_disk(&out_dir, &mut rendered)?;
+        }
+        edit(&mut rendered)?;
+        if steps.fmt {
+            format_files(
+                &mut rendered.files,
+                &file_origins(self.files(), &templates, ""),
+            )?;
+        }
+
+        // Only now that everything but writing succeeded.
+        if !preview {
+            if let Some(backup) = prepare_out_dir(&out_dir, args.overwrite_policy())? {
+                println!(
+                    "📦 Moved existing {} to {}",
+                    out_dir.to_string_lossy(),
+                    backup.to_string_lossy()
+                );
+            }
+        }
+
+        report_overrides(&rendered.overrides);
+        for note in &rendered.notes {
+            println!("ℹ️  {}", note);
+        }
+
+        if preview {
+            let changes = plan_changes(&out_dir, &rendered.files)?;
+            if args.diff {
+                print_diff(&changes);
+            }
+            if args.dry_run {
+                print_dry_run(&out_dir, &changes);
+            }
+            return Ok(out_dir);
+        }
+
+        self.write(&out_dir, &rendered)?;
+
+        println!(
+            "✅ Generated {} project in {}",
+            self.description(),
+            out_dir.to_string_lossy()
+        );
+
+        Ok(out_dir)
+    }
+}
+
+/// `schema` with the `kind` and `version` keys every config may have.
+pub(crate) fn with_kind_and_version(mut schema: Schema, kind: &str) -> Schema {
+    if let Some(properties) = schema.get_mut("properties").and_then(|p| p.as_object_mut()) {
+        properties.insert(
+            "kind".to_string(),
+            json!({
+                "description": "Generator for this config, used by `netgen generate`.",
+                "const": kind,
+            }),
+        );
+        properties.insert(
+            "version".to_string(),
+            json!({
+                "description": "Config schema version; 0 when missing. \
+                                `netgen migrate` upgrades older configs.",
+                "type": "integer",
+                "minimum": 0,
+                "maximum": CURRENT_VERSION,
+            }),
+        );
+    }
+    schema
+}
+
+/// Write `files` into `out_dir`, creating directories as needed.
+pub fn write_tree(out_dir: &Path, files: &FileTree) -> Result<()> {
+    for (path, contents) in files {
+        let path = out_dir.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+    }
+
+    Ok(())
+}
+
+/// Object-safe view of a [`Generator`], used by the [`Registry`].
+pub trait DynGenerator {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn kind(&self) -> String;
+    fn files(&self) -> &'static [OutputFile];
+    fn config_schema(&self) -> Schema;
+
+    /// Parse and validate `config` and render the project in memory.
+    /// Nothing is read from or written to the output directory.
+    fn render_config(&self, config: &ConfigSource, templates: &TemplateSet) -> Result<Rendered>;
+
+    /// Parse `config` and generate the project. Returns the output directory.
+    fn run(&self, config: &ConfigSource, args: &GenerateArgs) -> Result<PathBuf>;
+
+    /// Same as [`DynGenerator::run`], see [`Generator::run_with`].
+    fn run_with(
+        &self,
+        config: &ConfigSource,
+        args: &GenerateArgs,
+        edit: &mut dyn FnMut(&mut Rendered) -> Result<()>,
+    ) -> Result<PathBuf>;
+}
+
+impl<G: Generator> DynGenerator for G {
+    fn name(&self) -> &'static str {
+        Generator::name(self)
+    }
+
+    fn description(&self) -> &'static str {
+        Generator::description(self)
+    }
+
+    fn kind(&self) -> String {
+        Generator::kind(self)
+    }
+
+    fn files(&self) -> &'static [OutputFile] {
+        Generator::files(self)
+    }
+
+    fn config_schema(&self) -> Schema {
+        Generator::config_schema(self)
+    }
+
+    fn render_config(&self, config: &ConfigSource, templates: &TemplateSet) -> Result<Rendered> {
+        let cfg = load_config(self, config)?;
+        self.render(&self.context(cfg), templates)
+    }
+
+    fn run(&self, config: &ConfigSource, args: &GenerateArgs) -> Result<PathBuf> {
+        let cfg = load_config(self, config)?;
+        Generator::run(self, cfg, args).map_err(|err| with_config(err, &config.origin))
+    }
+
+    fn run_with(
+        &self,
+        config: &ConfigSource,
+        args: &GenerateArgs,
+        edit: &mut dyn FnMut(&mut Rendered) -> Result<()>,
+    ) -> Result<PathBuf> {
+        let cfg = load_config(self, config)?;
+        Generator::run_with(self, cfg, args, edit).map_err(|err| with_config(err, &config.origin))
+    }
+}
+
+/// Parse `config` for `generator`, checking its `kind` and contents.
+fn load_config<G: Generator>(generator: &G, config: &ConfigSource) -> Result<G::Config> {
+    // Checked first: a config for another generator rarely parses as this one's.
+    if let Some(kind) = config.kind()? {
+        let expected = generator.kind();
+        if kind != expected {
+            let issue = Issue::new(
+                "kind",
+                format!("config is for `{}`, not `{}`", kind, expected),
+            )
+            .suggest("run `netgen generate --config ...` to pick the generator from `kind`");
+            return Err(ValidationError::located(config, vec![issue]).into());
+        }
+    }
+
+    let cfg: G::Config = migrate::load(config)?;
+    check(config, generator.validate(&cfg))?;
+    Ok(cfg)
+}
+
+/// Set of available generators, looked up by name.
+#[derive(Default)]
+pub struct Registry {
+    generators: Vec<Box<dyn DynGenerator>>,
+}
+
+impl Registry {
+    /// Empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with all generators shipped with netgen.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(TcpEchoGenerator);
+        registry.register(TcpWorkerGenerator);
+        registry.register(HttpAxumGenerator);
+        registry
+    }
+
+    pub fn register<G: Generator + 'static>(&mut self, generator: G) {
+        self.generators.push(Box::new(generator));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn DynGenerator> {
+        self.generators
+            .iter()
+            .find(|g| g.name() == name)
+            .map(|g| g.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn DynGenerator> {
+        self.generators.iter().map(|g| g.as_ref())
+    }
+
+    /// Run generator `name` on `config`. Returns the output directory.
+    pub fn run(&self, name: &str, config: &ConfigSource, args: &GenerateArgs) -> Result<PathBuf> {
+        self.require(name)?.run(config, args)
+    }
+
+    /// Generator selected by the `kind` key of `config`.
+    pub fn for_config(&self, config: &ConfigSource) -> Result<&dyn DynGenerator> {
+        let kinds: Vec<_> = self.iter().map(|g| g.kind()).collect();
+
+        let issue = match config.kind()? {
+            Some(kind) => match self.for_kind(&kind) {
+                Some(generator) => return Ok(generator),
+                None => Issue::new("kind", format!("unknown kind `{}`", kind)),
+            },
+            None => Issue::new("kind", "missing `kind` key"),
+        };
+
+        let issue = issue.suggest(format!("kind: one of {}", kinds.join(", ")));
+        Err(ValidationError::located(config, vec![issue]).into())
+    }
+
+    /// Generator of configs with `kind: <kind>`, e.g. `tcp_echo`.
+    pub fn for_kind(&self, kind: &str) -> Option<&dyn DynGenerator> {
+        self.iter().find(|g| g.kind() == kind)
+    }
+
+    /// Generator `name`, or an error listing the available ones.
+    pub fn require(&self, name: &str) -> Result<&dyn DynGenerator> {
+        self.get(name).ok_or_else(|| {
+            let available: Vec<_> = self.iter().map(|g| g.name()).collect();
+            anyhow!(
+                "unknown generator '{}' (available: {})",
+                name,
+                available.join(", ")
+            )
+        })
+    }
+}
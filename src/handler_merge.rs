@@ -0,0 +1,381 @@
+// src/handler_merge.rs
+//! Keeps hand-written code when `src/handlers.rs` is regenerated.
+//!
+//! The generated file only consists of imports and top-level `fn` items, so a
+//! light scanner that understands comments, strings and braces is enough to
+//! find each item and function body; no full Rust parser is needed.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Top-level function found in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FnItem {
+    pub name: String,
+    /// Start of the item, including doc comments and attributes.
+    pub item_start: usize,
+    /// Body including the surrounding braces.
+    pub body: Range<usize>,
+}
+
+/// Top-level item found in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// Function with a body.
+    Fn(FnItem),
+    /// Any other item (`use`, `struct`, `impl`, `const`, ...), including doc
+    /// comments and attributes.
+    Other(Range<usize>),
+}
+
+/// Outcome of merging a freshly rendered handlers file with the one on disk.
+#[derive(Debug, Clone, Default)]
+pub struct HandlerMerge {
+    pub contents: String,
+    /// Handlers whose existing bodies were kept.
+    pub kept: Vec<String>,
+    /// Handlers that did not exist before.
+    pub added: Vec<String>,
+    /// Handlers no longer in the config. Their code is kept at the end of the file.
+    pub removed: Vec<String>,
+    /// First lines of the other items that were only in the file on disk,
+    /// e.g. imports and helper types. They are kept too.
+    pub carried: Vec<String>,
+}
+
+/// Merge `rendered` (fresh template output) with `existing` (file on disk).
+///
+/// Signatures always come from the template; bodies of handlers that already
+/// exist are taken from `existing`. Handlers that are only in `existing` are
+/// appended unchanged, and so are its other items unless `rendered` has them
+/// too or they are `generated`: items the template writes for other configs,
+/// which it adds and removes itself. Imports go after the rendered ones.
+pub fn merge_handlers(rendered: &str, existing: &str, generated: &[String]) -> HandlerMerge {
+    let new_items = top_level_items(rendered);
+    let new_fns: Vec<&FnItem> = fns(&new_items).collect();
+    let old_items = top_level_items(existing);
+    let old_by_name: HashMap<&str, &FnItem> =
+        fns(&old_items).map(|f| (f.name.as_str(), f)).collect();
+
+    // Items the template writes; the other items of `existing` are the user's.
+    let owned: Vec<&str> = others(&new_items, rendered)
+        .chain(generated.iter().map(|item| item.trim()))
+        .collect();
+    let (imports, carried): (Vec<&str>, Vec<&str>) = others(&old_items, existing)
+        .filter(|item| !owned.contains(item))
+        .partition(|item| is_import(item));
+
+    let mut merge = HandlerMerge::default();
+    let mut pos = 0;
+
+    // Carried imports follow the last rendered one, or start the file.
+    let imports_at = new_items.iter().rev().find_map(|item| match item {
+        Item::Other(span) if is_import(&rendered[span.clone()]) => Some(span.end),
+        _ => None,
+    });
+    if !imports.is_empty() {
+        let at = imports_at.unwrap_or(0);
+        merge.contents.push_str(&rendered[..at]);
+        if at > 0 {
+            merge.contents.push('\n');
+        }
+        merge.contents.push_str(&imports.join("\n"));
+        if at == 0 {
+            merge.contents.push_str("\n\n");
+        }
+        pos = at;
+    }
+
+    for new_fn in &new_fns {
+        match old_by_name.get(new_fn.name.as_str()) {
+            Some(old_fn) => {
+                merge.contents.push_str(&rendered[pos..new_fn.body.start]);
+                merge.contents.push_str(&existing[old_fn.body.clone()]);
+                pos = new_fn.body.end;
+                merge.kept.push(new_fn.name.clone());
+            }
+            None => merge.added.push(new_fn.name.clone()),
+        }
+    }
+    merge.contents.push_str(&rendered[pos..]);
+
+    for old_item in &old_items {
+        let code = match old_item {
+            Item::Fn(old_fn) if new_fns.iter().all(|f| f.name != old_fn.name) => {
+                merge.removed.push(old_fn.name.clone());
+                &existing[old_fn.item_start..old_fn.body.end]
+            }
+            Item::Other(span) if carried.contains(&existing[span.clone()].trim()) => {
+                let code = existing[span.clone()].trim();
+                merge.carried.push(first_line(code).to_string());
+                code
+            }
+            _ => continue,
+        };
+
+        if !merge.contents.ends_with("\n\n") {
+            merge.contents.push('\n');
+        }
+        merge.contents.push_str(code);
+        merge.contents.push('\n');
+    }
+    merge.carried.splice(
+        0..0,
+        imports.iter().map(|import| first_line(import).to_string()),
+    );
+
+    merge
+}
+
+/// Functions among `items`.
+fn fns(items: &[Item]) -> impl Iterator<Item = &FnItem> {
+    items.iter().filter_map(|item| match item {
+        Item::Fn(f) => Some(f),
+        Item::Other(_) => None,
+    })
+}
+
+/// Code of the items of `src` other than functions, trimmed.
+fn others<'a>(items: &'a [Item], src: &'a str) -> impl Iterator<Item = &'a str> {
+    items.iter().filter_map(move |item| match item {
+        Item::Other(span) => Some(src[span.clone()].trim()),
+        Item::Fn(_) => None,
+    })
+}
+
+/// Whether `item` is a `use` declaration.
+fn is_import(item: &str) -> bool {
+    let code = item
+        .lines()
+        .map(str::trim_start)
+        .find(|line| !line.starts_with("//") && !line.starts_with("#["))
+        .unwrap_or_default();
+    code.starts_with("use ") || code.starts_with("pub use ")
+}
+
+/// First line of `item` that is neither a comment nor an attribute.
+fn first_line(item: &str) -> &str {
+    item.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("//") && !line.starts_with("#["))
+        .unwrap_or(item)
+}
+
+/// Find all top-level (brace depth 0) functions with a body.
+pub fn top_level_fns(src: &str) -> Vec<FnItem> {
+    fns(&top_level_items(src)).cloned().collect()
+}
+
+/// Find all top-level (brace depth 0) items. Comments after the last item
+/// count as one more item.
+pub fn top_level_items(src: &str) -> Vec<Item> {
+    let bytes = src.as_bytes();
+    let mut items = Vec::new();
+
+    let mut i = 0;
+    let mut depth = 0usize;
+    // End of the previous top-level item; the next item starts here.
+    let mut boundary = 0;
+    // Name and item start of a `fn` whose body has not been seen yet.
+    let mut pending: Option<(String, usize)> = None;
+    let mut body_start = None;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = skip_line_comment(bytes, i);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                continue;
+            }
+            b'"' => {
+                i = skip_string(bytes, i + 1);
+                continue;
+            }
+            b'\'' => {
+                i = skip_char_or_lifetime(bytes, i);
+                continue;
+            }
+            b'{' => {
+                if depth == 0 && pending.is_some() {
+                    body_start = Some(i);
+                }
+                depth += 1;
+            }
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    if let (Some((name, item_start)), Some(start)) =
+                        (pending.take(), body_start.take())
+                    {
+                        items.push(Item::Fn(FnItem {
+                            name,
+                            item_start: skip_whitespace(bytes, item_start),
+                            body: start..i + 1,
+                        }));
+                        boundary = i + 1;
+                    } else if bytes.get(skip_whitespace(bytes, i + 1)) != Some(&b';') {
+                        // A braced item, unless a `;` ends it (e.g. `const X: S = S {};`).
+                        items.push(Item::Other(skip_whitespace(bytes, boundary)..i + 1));
+                        boundary = i + 1;
+                    }
+                }
+            }
+            b';' if depth == 0 => {
+                // `fn` without a body (e.g. in `extern` blocks) or any other item.
+                pending = None;
+                items.push(Item::Other(skip_whitespace(bytes, boundary)..i + 1));
+                boundary = i + 1;
+            }
+            _ if is_ident_start(c) && (i == 0 || !is_ident_char(bytes[i - 1])) => {
+                if let Some(end) = raw_string_end(bytes, i) {
+                    i = end;
+                    continue;
+                }
+
+                let end = ident_end(bytes, i);
+                if depth == 0 && &src[i..end] == "fn" {
+                    let name_start = skip_whitespace(bytes, end);
+                    let name_end = ident_end(bytes, name_start);
+                    if name_end > name_start {
+                        pending = Some((src[name_start..name_end].to_string(), boundary));
+                    }
+                    i = name_end.max(end);
+                } else {
+                    i = end;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let rest = skip_whitespace(bytes, boundary);
+    if rest < bytes.len() {
+        items.push(Item::Other(rest..bytes.len()));
+    }
+
+    items
+}
+
+fn is_ident_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn is_ident_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+fn ident_end(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && is_ident_char(bytes[i]) {
+        i += 1;
+    }
+    i
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+fn skip_line_comment(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i] != b'\n' {
+        i += 1;
+    }
+    i
+}
+
+fn skip_block_comment(bytes: &[u8], mut i: usize) -> usize {
+    // Block comments nest in Rust.
+    let mut nesting = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            nesting += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            nesting -= 1;
+            i += 2;
+            if nesting == 0 {
+                break;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    i
+}
+
+/// Skip a string body starting right after the opening quote.
+fn skip_string(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// `'a'`, `'\n'` and `'\u{1F600}'` are chars; `'a` is a lifetime.
+fn skip_char_or_lifetime(bytes: &[u8], i: usize) -> usize {
+    if bytes.get(i + 1) == Some(&b'\\') {
+        // Skip the escaped char itself so `'\''` is handled.
+        let mut j = i + 3;
+        while j < bytes.len() && bytes[j] != b'\'' {
+            j += 1;
+        }
+        return j + 1;
+    }
+
+    // Multi-byte UTF-8 chars: find the closing quote within one code point.
+    let mut j = i + 2;
+    while j < bytes.len() && j <= i + 5 {
+        if bytes[j] == b'\'' {
+            return j + 1;
+        }
+        if bytes[j].is_ascii() {
+            break;
+        }
+        j += 1;
+    }
+
+    i + 1
+}
+
+/// If a raw (byte) string starts at `i` (`r"..."`, `r#"..."#`, `br"..."`),
+/// return the index right after it.
+fn raw_string_end(bytes: &[u8], i: usize) -> Option<usize> {
+    let mut j = i;
+    if bytes.get(j) == Some(&b'b') {
+        j += 1;
+    }
+    if bytes.get(j) != Some(&b'r') {
+        return None;
+    }
+    j += 1;
+
+    let mut hashes = 0;
+    while bytes.get(j) == Some(&b'#') {
+        hashes += 1;
+        j += 1;
+    }
+    if bytes.get(j) != Some(&b'"') {
+        return None;
+    }
+    j += 1;
+
+    while j < bytes.len() {
+        if bytes[j] == b'"' && bytes[j + 1..].iter().take_while(|&&b| b == b'#').count() >= hashes {
+            return Some(j + 1 + hashes);
+        }
+        j += 1;
+    }
+    Some(j)
+}
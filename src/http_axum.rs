@@ -1,26 +1,38 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use clap::Args;
 use handlebars::Handlebars;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::util::{read_yaml, resolve_out_dir};
+use crate::config::ConfigFormat;
+use crate::connection::{default_shutdown_grace, ConfigDuration};
+use crate::generator::{FileTree, GenerateArgs, Generator, OutputFile, ProjectSettings};
+use crate::handler_merge::{merge_handlers, top_level_items, Item};
+use crate::post_generate::PostStep;
+use crate::templates::{self, TemplateSet};
+use crate::validate::{check_crate_name, check_identifier, Issue};
 
 /// Subcommand for generating HTTP service on axum.
 #[derive(Debug, Args)]
 pub struct HttpAxumCmd {
-    /// Path to YAML config (required).
+    /// Path to config file (YAML, TOML or JSON).
     #[arg(long)]
     pub config: String,
 
-    /// Override output directory.
-    #[arg(long)]
-    pub out_dir: Option<String>,
+    /// Config format (detected from the file extension by default).
+    #[arg(long, value_enum)]
+    pub format: Option<ConfigFormat>,
+
+    #[command(flatten)]
+    pub generate: GenerateArgs,
 }
 
 /// HTTP method in YAML config.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum HttpMethod {
     GET,
@@ -29,10 +41,23 @@ pub enum HttpMethod {
     DELETE,
 }
 
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::GET => "GET",
+            HttpMethod::POST => "POST",
+            HttpMethod::PUT => "PUT",
+            HttpMethod::DELETE => "DELETE",
+        }
+    }
+}
+
 /// Description of a single route in YAML.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct HttpRouteYaml {
+    /// Route path, e.g. `/health`.
     pub path: String,
+    /// HTTP method.
     pub method: HttpMethod,
     /// Handler name that will be generated.
     pub handler: String,
@@ -41,7 +66,7 @@ pub struct HttpRouteYaml {
 }
 
 /// Database config in YAML.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct DatabaseYamlConfig {
     /// Enable database initialization.
     pub enabled: bool,
@@ -58,15 +83,30 @@ fn default_db_kind() -> String {
     "postgres".to_string()
 }
 
-#[derive(Debug, Deserialize)]
+/// HTTP axum service config.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct HttpAxumYamlConfig {
+    /// Crate name of the generated project.
     pub project_name: String,
+    /// Port to listen on.
     pub port: u16,
+    /// Log through `tracing` instead of stdout/stderr.
     pub tracing: bool,
+    /// Routes and their stub handlers.
     pub routes: Vec<HttpRouteYaml>,
+    /// Output directory (defaults to `project_name`).
     pub out_dir: Option<String>,
     /// Optional database block.
     pub database: Option<DatabaseYamlConfig>,
+    /// How long requests in flight may take to finish after SIGINT or SIGTERM
+    /// before the server exits anyway (default `10s`).
+    #[serde(default = "default_shutdown_grace")]
+    pub shutdown_grace: ConfigDuration,
+    /// Directory with template overrides.
+    pub templates_dir: Option<String>,
+    /// Steps run after generating: `fmt` (rustfmt) and/or `check` (`cargo check`).
+    #[serde(default)]
+    pub post_generate: Vec<PostStep>,
 }
 
 /// Route as it appears in the template.
@@ -93,6 +133,9 @@ pub struct HttpAxumTemplateCtx {
     pub db_enabled: bool,
     pub db_url_env: Option<String>,
     pub db_max_connections: Option<u32>,
+
+    /// Rust expression of `shutdown_grace`.
+    pub shutdown_grace: String,
 }
 
 impl From<HttpAxumYamlConfig> for HttpAxumTemplateCtx {
@@ -101,13 +144,7 @@ impl From<HttpAxumYamlConfig> for HttpAxumTemplateCtx {
             .routes
             .into_iter()
             .map(|r| {
-                let method_fn = match r.method {
-                    HttpMethod::GET => "get",
-                    HttpMethod::POST => "post",
-                    HttpMethod::PUT => "put",
-                    HttpMethod::DELETE => "delete",
-                }
-                .to_string();
+                let method_fn = r.method.as_str().to_ascii_lowercase();
 
                 RouteTemplate {
                     path: r.path,
@@ -136,59 +173,243 @@ impl From<HttpAxumYamlConfig> for HttpAxumTemplateCtx {
             db_enabled,
             db_url_env,
             db_max_connections,
+            shutdown_grace: cfg.shutdown_grace.rust_expr(),
         }
     }
 }
 
-/// Generate HTTP axum service project from template context.
-pub fn generate_http_axum_project(ctx: &HttpAxumTemplateCtx, out_dir: &Path) -> Result<()> {
-    let src_dir = out_dir.join("src");
-    std::fs::create_dir_all(&src_dir)?;
+/// Generator for HTTP services on axum.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpAxumGenerator;
 
-    let mut hbs = Handlebars::new();
-    // Disable HTML escaping since we're generating code, not HTML
-    hbs.register_escape_fn(handlebars::no_escape);
+const FILES: &[OutputFile] = &[
+    OutputFile {
+        template: "http_axum/Cargo.toml.hbs",
+        path: "Cargo.toml",
+    },
+    OutputFile {
+        template: "http_axum/main.rs.hbs",
+        path: "src/main.rs",
+    },
+    OutputFile {
+        template: "http_axum/handlers.rs.hbs",
+        path: "src/handlers.rs",
+    },
+];
 
-    hbs.register_template_string(
-        "cargo_toml",
-        include_str!("../templates/http_axum/Cargo.toml.hbs"),
-    )?;
-    hbs.register_template_string(
-        "main_rs",
-        include_str!("../templates/http_axum/main.rs.hbs"),
-    )?;
-    hbs.register_template_string(
-        "handlers_rs",
-        include_str!("../templates/http_axum/handlers.rs.hbs"),
-    )?;
+impl Generator for HttpAxumGenerator {
+    type Config = HttpAxumYamlConfig;
+    type Ctx = HttpAxumTemplateCtx;
 
-    let cargo_toml = hbs.render("cargo_toml", ctx)?;
-    std::fs::write(out_dir.join("Cargo.toml"), cargo_toml)?;
+    fn name(&self) -> &'static str {
+        "http-axum"
+    }
 
-    let main_rs = hbs.render("main_rs", ctx)?;
-    std::fs::write(src_dir.join("main.rs"), main_rs)?;
+    fn description(&self) -> &'static str {
+        "HTTP axum service"
+    }
 
-    let handlers_rs = hbs.render("handlers_rs", ctx)?;
-    std::fs::write(src_dir.join("handlers.rs"), handlers_rs)?;
+    fn files(&self) -> &'static [OutputFile] {
+        FILES
+    }
 
-    Ok(())
-}
+    fn partials(&self) -> &'static [&'static str] {
+        &["shutdown/signal.rs.hbs"]
+    }
+
+    fn settings(&self, cfg: &HttpAxumYamlConfig) -> ProjectSettings {
+        ProjectSettings {
+            project_name: cfg.project_name.clone(),
+            out_dir: cfg.out_dir.clone(),
+            templates_dir: cfg.templates_dir.clone(),
+            post_generate: cfg.post_generate.clone(),
+        }
+    }
+
+    fn context(&self, cfg: HttpAxumYamlConfig) -> HttpAxumTemplateCtx {
+        cfg.into()
+    }
+
+    fn validate(&self, cfg: &HttpAxumYamlConfig) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        issues.extend(check_crate_name("project_name", &cfg.project_name));
+
+        if cfg.routes.is_empty() {
+            issues.push(
+                Issue::new("routes", "at least one route is required").suggest(
+                    "add a route, e.g. `- { path: /, method: GET, handler: root, response: OK }`",
+                ),
+            );
+        }
+
+        let mut handlers: HashMap<&str, usize> = HashMap::new();
+        let mut endpoints: HashMap<(&str, &str), usize> = HashMap::new();
+
+        for (i, route) in cfg.routes.iter().enumerate() {
+            let prefix = format!("routes[{}]", i);
+
+            if !route.path.starts_with('/') {
+                issues.push(
+                    Issue::new(
+                        format!("{}.path", prefix),
+                        format!("route path `{}` must start with `/`", route.path),
+                    )
+                    .suggest(format!("path: /{}", route.path)),
+                );
+            }
 
-/// Entrypoint для subcommand `http-axum`.
-pub fn run_from_cli(cmd: HttpAxumCmd) -> Result<()> {
-    let cfg: HttpAxumYamlConfig = read_yaml(&cmd.config)?;
-    let cfg_out_dir = cfg.out_dir.clone();
-    let ctx: HttpAxumTemplateCtx = cfg.into();
+            let method = route.method.as_str();
+            if let Some(first) = endpoints.insert((route.path.as_str(), method), i) {
+                issues.push(Issue::new(
+                    format!("{}.method", prefix),
+                    format!(
+                        "{} {} is already defined by routes[{}]",
+                        method, route.path, first
+                    ),
+                ));
+            }
+
+            let handler_path = format!("{}.handler", prefix);
+            issues.extend(check_identifier(&handler_path, &route.handler));
+            if let Some(first) = handlers.insert(route.handler.as_str(), i) {
+                issues.push(
+                    Issue::new(
+                        handler_path,
+                        format!(
+                            "handler `{}` is already used by routes[{}]",
+                            route.handler, first
+                        ),
+                    )
+                    .suggest(format!(
+                        "handler: {}_{}",
+                        route.handler,
+                        method.to_ascii_lowercase()
+                    )),
+                );
+            }
+
+            if route.response.contains(['"', '\\']) {
+                issues.push(
+                    Issue::new(
+                        format!("{}.response", prefix),
+                        "response must not contain `\"` or `\\`; it is emitted as a Rust string literal",
+                    )
+                    .suggest("use single quotes instead of double quotes"),
+                );
+            }
+        }
+
+        if let Some(db) = &cfg.database {
+            let valid_env = !db.url_env.is_empty()
+                && !db.url_env.starts_with(|c: char| c.is_ascii_digit())
+                && db
+                    .url_env
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_env {
+                issues.push(
+                    Issue::new(
+                        "database.url_env",
+                        format!("`{}` is not a valid environment variable name", db.url_env),
+                    )
+                    .suggest("url_env: DATABASE_URL"),
+                );
+            }
+            if db.max_connections == Some(0) {
+                issues.push(
+                    Issue::new("database.max_connections", "must be at least 1")
+                        .suggest("max_connections: 10"),
+                );
+            }
+        }
 
-    let out_dir_str = resolve_out_dir(cmd.out_dir.clone(), cfg_out_dir, &ctx.project_name);
-    let out_dir = Path::new(&out_dir_str);
+        issues
+    }
 
-    generate_http_axum_project(&ctx, out_dir)?;
+    /// Keep hand-written handler bodies in `src/handlers.rs`.
+    fn merge_existing(
+        &self,
+        path: &Path,
+        rendered: String,
+        existing: &str,
+        notes: &mut Vec<String>,
+    ) -> String {
+        if path != Path::new("src/handlers.rs") {
+            return rendered;
+        }
 
-    println!(
-        "✅ Generated HTTP axum project in {}",
-        out_dir.to_string_lossy()
-    );
+        let merge = merge_handlers(&rendered, existing, &template_items());
+        if !merge.kept.is_empty() {
+            notes.push(format!(
+                "kept existing bodies of {} handler(s) in src/handlers.rs",
+                merge.kept.len()
+            ));
+        }
+        for name in &merge.added {
+            notes.push(format!("added handler `{}`", name));
+        }
+        for name in &merge.removed {
+            notes.push(format!(
+                "handler `{}` is no longer in the config; its code was kept in src/handlers.rs",
+                name
+            ));
+        }
+        if !merge.carried.is_empty() {
+            notes.push(format!(
+                "kept {} item(s) not generated from the config in src/handlers.rs: `{}`",
+                merge.carried.len(),
+                merge.carried.join("`, `")
+            ));
+        }
 
+        merge.contents
+    }
+}
+
+/// Items other than handlers that the embedded `src/handlers.rs` template
+/// writes, with or without a database. They are the template's to add and
+/// remove when regenerating; any other item in the file is the user's.
+fn template_items() -> Vec<String> {
+    let template = templates::embedded("http_axum/handlers.rs.hbs").unwrap_or_default();
+    let mut hbs = Handlebars::new();
+    hbs.register_escape_fn(handlebars::no_escape);
+    let mut items = Vec::new();
+    for db_enabled in [false, true] {
+        let ctx = json!({ "db_enabled": db_enabled, "routes": [] });
+        let Ok(src) = hbs.render_template(template, &ctx) else {
+            continue;
+        };
+        items.extend(
+            top_level_items(&src)
+                .into_iter()
+                .filter_map(|item| match item {
+                    Item::Other(span) => Some(src[span].to_string()),
+                    Item::Fn(_) => None,
+                }),
+        );
+    }
+    items
+}
+
+/// Render HTTP axum service project in memory, keyed by path relative to the project root.
+pub fn render_http_axum_project(ctx: &HttpAxumTemplateCtx) -> Result<FileTree> {
+    Ok(HttpAxumGenerator
+        .render(ctx, &TemplateSet::embedded())?
+        .files)
+}
+
+/// Generate HTTP axum service project from template context.
+pub fn generate_http_axum_project(ctx: &HttpAxumTemplateCtx, out_dir: &Path) -> Result<()> {
+    generate_http_axum_project_with_templates(ctx, out_dir, &TemplateSet::embedded())?;
     Ok(())
 }
+
+/// Same as [`generate_http_axum_project`], but loads templates from `templates`.
+/// Returns the template overrides that were used.
+pub fn generate_http_axum_project_with_templates(
+    ctx: &HttpAxumTemplateCtx,
+    out_dir: &Path,
+    templates: &TemplateSet,
+) -> Result<Vec<PathBuf>> {
+    HttpAxumGenerator.generate(ctx, out_dir, templates)
+}
@@ -3,8 +3,21 @@
 //! This library provides functionality to generate network service code
 //! including TCP echo servers, TCP worker-pool servers, and HTTP Axum services.
 
+pub mod config;
+pub mod connection;
+pub mod generator;
+pub mod handler_merge;
 pub mod http_axum;
+pub mod lock;
+pub mod migrate;
+pub mod out_dir;
+pub mod post_generate;
+pub mod preview;
 pub mod read_mode;
+pub mod status;
 pub mod tcp_echo;
 pub mod tcp_worker;
+pub mod templates;
 pub mod util;
+pub mod validate;
+pub mod workspace;
@@ -0,0 +1,94 @@
+// src/lock.rs
+//! `.netgen/lock.json`: what a project was generated from and what was written.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::generator::FileTree;
+use crate::out_dir::MARKER_DIR;
+
+/// Lockfile name inside [`MARKER_DIR`].
+pub const LOCK_FILE: &str = "lock.json";
+
+/// Contents of `.netgen/lock.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lock {
+    /// netgen version that generated the project.
+    pub netgen_version: String,
+    /// Generator name (e.g. `tcp-echo`), or `workspace`.
+    pub generator: String,
+    /// Hash of the resolved config.
+    pub config_hash: String,
+    pub templates: TemplatesLock,
+    /// Checksum of every generated file, keyed by path relative to the project root.
+    pub files: BTreeMap<String, String>,
+}
+
+/// Templates a project was rendered with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplatesLock {
+    /// Override directory, if one was used.
+    pub overrides_dir: Option<String>,
+    /// Checksum of every template source, keyed by path relative to `templates/`.
+    pub checksums: BTreeMap<String, String>,
+}
+
+impl Lock {
+    pub fn new(
+        generator: &str,
+        config_hash: String,
+        templates: TemplatesLock,
+        files: &FileTree,
+    ) -> Self {
+        Self {
+            netgen_version: env!("CARGO_PKG_VERSION").to_string(),
+            generator: generator.to_string(),
+            config_hash,
+            templates,
+            files: files
+                .iter()
+                .map(|(path, contents)| (path.clone(), checksum(contents)))
+                .collect(),
+        }
+    }
+}
+
+/// `sha256:<hex>` of `data`.
+pub fn checksum(data: impl AsRef<[u8]>) -> String {
+    let digest = Sha256::digest(data.as_ref());
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256:{}", hex)
+}
+
+pub fn lock_path(out_dir: &Path) -> PathBuf {
+    out_dir.join(MARKER_DIR).join(LOCK_FILE)
+}
+
+/// Write `lock` into `out_dir`.
+pub fn write_lock(out_dir: &Path, lock: &Lock) -> Result<()> {
+    std::fs::create_dir_all(out_dir.join(MARKER_DIR))?;
+
+    let json = serde_json::to_string_pretty(lock)?;
+    std::fs::write(lock_path(out_dir), json + "\n")?;
+    Ok(())
+}
+
+/// Read the lock of the project in `out_dir`.
+pub fn read_lock(out_dir: &Path) -> Result<Lock> {
+    let path = lock_path(out_dir);
+    if !path.is_file() {
+        bail!(
+            "{} has no {}; was it generated by netgen?",
+            out_dir.display(),
+            path.display()
+        );
+    }
+
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))
+}
@@ -1,9 +1,16 @@
+use std::path::Path;
+
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
-use netgen::http_axum::{self, HttpAxumCmd};
-use netgen::tcp_echo::{self, EchoCmd};
-use netgen::tcp_worker::{self, WorkerCmd};
+use netgen::config::ConfigSource;
+use netgen::generator::{GenerateCmd, Registry};
+use netgen::http_axum::HttpAxumCmd;
+use netgen::migrate::{run_migrate, MigrateCmd};
+use netgen::status::{print_status, project_status, StatusCmd};
+use netgen::tcp_echo::EchoCmd;
+use netgen::tcp_worker::WorkerCmd;
+use netgen::workspace::{generate_workspace, workspace_schema, WORKSPACE_KIND};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -19,6 +26,9 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Command {
+    /// Generate the project described by a config with a `kind` key
+    Generate(GenerateCmd),
+
     /// Generate TCP echo server
     TcpEcho(EchoCmd),
 
@@ -27,14 +37,81 @@ enum Command {
 
     /// Generate HTTP service on axum
     HttpAxum(HttpAxumCmd),
+
+    /// List available generators
+    List,
+
+    /// Report whether a generated project is stale and which files were edited by hand
+    Status(StatusCmd),
+
+    /// Upgrade a config to the current schema version
+    Migrate(MigrateCmd),
+
+    /// Print the JSON Schema of a generator's config or of a workspace manifest
+    Schema {
+        /// Config kind (`tcp_echo`, `workspace`, ...) or generator name (see `netgen list`)
+        kind: String,
+    },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let registry = Registry::builtin();
+
+    let (name, config, args) = match cli.cmd {
+        Command::Generate(cmd) => {
+            let config = ConfigSource::read(&cmd.config, cmd.format)?;
+            if config.kind()?.as_deref() == Some(WORKSPACE_KIND) {
+                generate_workspace(&registry, &config, &cmd.generate)?;
+                return Ok(());
+            }
+            let name = registry.for_config(&config)?.name();
+            (name, config, cmd.generate)
+        }
+        Command::TcpEcho(cmd) => ("tcp-echo", cmd.config_source()?, cmd.generate),
+        Command::TcpWorker(cmd) => (
+            "tcp-worker",
+            ConfigSource::read(&cmd.config, cmd.format)?,
+            cmd.generate,
+        ),
+        Command::HttpAxum(cmd) => (
+            "http-axum",
+            ConfigSource::read(&cmd.config, cmd.format)?,
+            cmd.generate,
+        ),
+        Command::List => {
+            for generator in registry.iter() {
+                println!("{:<12} {}", generator.name(), generator.description());
+            }
+            return Ok(());
+        }
+        Command::Status(cmd) => {
+            let config = ConfigSource::read(&cmd.config, cmd.format)?;
+            let out_dir = cmd.out_dir.as_deref().map(Path::new);
+            for status in project_status(&registry, &config, out_dir)? {
+                print_status(&status);
+            }
+            return Ok(());
+        }
+        Command::Migrate(cmd) => {
+            run_migrate(&cmd)?;
+            return Ok(());
+        }
+        Command::Schema { kind } => {
+            let schema = if kind == WORKSPACE_KIND {
+                workspace_schema()
+            } else {
+                match registry.for_kind(&kind) {
+                    Some(generator) => generator.config_schema(),
+                    None => registry.require(&kind)?.config_schema(),
+                }
+            };
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        }
+    };
+
+    registry.run(name, &config, &args)?;
 
-    match cli.cmd {
-        Command::TcpEcho(cmd) => tcp_echo::run_from_cli(cmd),
-        Command::TcpWorker(cmd) => tcp_worker::run_from_cli(cmd),
-        Command::HttpAxum(cmd) => http_axum::run_from_cli(cmd),
-    }
+    Ok(())
 }
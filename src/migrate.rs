@@ -0,0 +1,487 @@
+// src/migrate.rs
+//! Config schema versions and `netgen migrate`.
+//!
+//! Configs carry a top-level `version` key; a config without one is version 0.
+//! Older versions keep loading: [`load`] applies the pending migrations in
+//! memory. `netgen migrate` writes them back to the file, editing the text in
+//! place so that comments and layout survive where possible.
+
+use std::fmt;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::config::{ConfigFormat, ConfigSource};
+use crate::preview::{unified_diff, FileChange, FileStatus};
+use crate::validate::{yaml_positions, Issue, ValidationError};
+
+/// Schema version written by this netgen.
+pub const CURRENT_VERSION: u64 = 1;
+
+/// Shape changes upgrading a config from one version to the next. The
+/// `version` key itself is updated separately.
+pub type Step = fn(&Value) -> Vec<Edit>;
+
+/// Steps of this netgen, indexed by the version they upgrade from.
+pub const MIGRATIONS: [Step; CURRENT_VERSION as usize] = [
+    // Version 1 only introduced the `version` key.
+    |_| Vec::new(),
+];
+
+/// `netgen migrate`: rewrite a config to the current schema version.
+#[derive(Args, Debug)]
+pub struct MigrateCmd {
+    /// Config to migrate (YAML, TOML or JSON). It is rewritten in place.
+    #[arg(long)]
+    pub config: String,
+
+    /// Config format (detected from the file extension by default).
+    #[arg(long, value_enum)]
+    pub format: Option<ConfigFormat>,
+
+    /// Print the changes as a diff instead of writing the file.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// A change to one key of a config. Paths are lists of mapping keys.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Add the key (as the first one of its mapping) or replace its value.
+    Set { path: Vec<String>, value: Value },
+    /// Rename the last key of `path` to `to`, keeping its value and position.
+    Rename { path: Vec<String>, to: String },
+    /// Remove the key and its value.
+    Remove { path: Vec<String> },
+}
+
+/// A [`Change`] and why a migration made it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub change: Change,
+    pub reason: String,
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.change {
+            Change::Set { path, value } => write!(f, "set `{}` to {}", path.join("."), value)?,
+            Change::Rename { path, to } => write!(f, "renamed `{}` to `{}`", path.join("."), to)?,
+            Change::Remove { path } => write!(f, "removed `{}`", path.join("."))?,
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+/// Result of migrating a config.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    /// Version the config was at.
+    pub from: u64,
+    /// Every change made, in order. Empty if the config was already current.
+    pub edits: Vec<Edit>,
+    /// The migrated config.
+    pub value: Value,
+    /// Text of the migrated config, in its original format.
+    pub text: String,
+    /// The text could not be edited in place and was serialized again,
+    /// dropping comments.
+    pub rewritten: bool,
+}
+
+/// Parse `config` into `T`, migrating it in memory first if it is older than
+/// [`CURRENT_VERSION`].
+pub fn load<T: DeserializeOwned>(config: &ConfigSource) -> Result<T> {
+    let value: Value = config.parse()?;
+    let from = version(config, &value, CURRENT_VERSION)?;
+    let (value, edits) = upgrade(value, &MIGRATIONS[from as usize..]);
+
+    // Without shape changes, parse the text itself for located errors.
+    if edits.is_empty() {
+        return config.parse();
+    }
+    serde_json::from_value(value).with_context(|| {
+        format!(
+            "failed to parse {} (migrated from version {})",
+            config.origin, from
+        )
+    })
+}
+
+/// Migrate `config` to [`CURRENT_VERSION`]. Nothing is written.
+pub fn migrate(config: &ConfigSource) -> Result<Migration> {
+    migrate_with(config, &MIGRATIONS)
+}
+
+/// Migrate `config` through `steps`, where `steps[n]` upgrades version `n`;
+/// the last version is `steps.len()`. Nothing is written.
+pub fn migrate_with(config: &ConfigSource, steps: &[Step]) -> Result<Migration> {
+    let current = steps.len() as u64;
+    let original: Value = config.parse()?;
+    let from = version(config, &original, current)?;
+    if from == current {
+        return Ok(Migration {
+            from,
+            edits: Vec::new(),
+            value: original,
+            text: config.text.clone(),
+            rewritten: false,
+        });
+    }
+
+    let (value, mut edits) = upgrade(original, &steps[from as usize..]);
+    edits.push(Edit {
+        change: Change::Set {
+            path: vec!["version".to_string()],
+            value: current.into(),
+        },
+        reason: if from == 0 {
+            "configs now record the schema version they follow".to_string()
+        } else {
+            format!("the config now follows schema version {}", current)
+        },
+    });
+    // The version is set on the text as well.
+    let mut expected = value;
+    apply_value(
+        &mut expected,
+        &edits.last().expect("version edit was just pushed").change,
+    );
+
+    let changes: Vec<&Change> = edits.iter().map(|edit| &edit.change).collect();
+    let edited = match config.format {
+        ConfigFormat::Yaml => edit_yaml(&config.text, &changes)
+            .filter(|text| serde_yaml::from_str::<Value>(text).ok().as_ref() == Some(&expected)),
+        ConfigFormat::Toml => edit_toml(&config.text, &changes)
+            .filter(|text| toml::from_str::<Value>(text).ok().as_ref() == Some(&expected)),
+        // JSON has no comments; with ordered maps it round-trips as is.
+        ConfigFormat::Json => Some(serde_json::to_string_pretty(&expected)? + "\n"),
+    };
+
+    let (text, rewritten) = match edited {
+        Some(text) => (text, false),
+        None => {
+            let text = match config.format {
+                ConfigFormat::Yaml => serde_yaml::to_string(&expected)?,
+                ConfigFormat::Toml => toml::to_string_pretty(&expected)?,
+                ConfigFormat::Json => serde_json::to_string_pretty(&expected)? + "\n",
+            };
+            (text, true)
+        }
+    };
+
+    Ok(Migration {
+        from,
+        edits,
+        value: expected,
+        text,
+        rewritten,
+    })
+}
+
+/// Run `netgen migrate`.
+pub fn run_migrate(cmd: &MigrateCmd) -> Result<()> {
+    let config = ConfigSource::read(&cmd.config, cmd.format)?;
+    let migration = migrate(&config)?;
+
+    if migration.edits.is_empty() {
+        println!(
+            "✅ {} is already at version {}",
+            config.origin, CURRENT_VERSION
+        );
+        return Ok(());
+    }
+
+    println!(
+        "🔁 Migrating {} from version {} to {}:",
+        config.origin, migration.from, CURRENT_VERSION
+    );
+    for edit in &migration.edits {
+        println!("  - {}", edit);
+    }
+    if migration.rewritten {
+        println!(
+            "⚠️  {} could not be edited in place; it is written out again without its comments",
+            config.origin
+        );
+    }
+
+    if cmd.dry_run {
+        let change = FileChange {
+            path: config.origin.clone().into(),
+            status: FileStatus::Update,
+            old: Some(config.text.clone()),
+            new: migration.text,
+        };
+        if let Some(diff) = unified_diff(&change) {
+            print!("{}", diff);
+        }
+        println!("🔍 Dry run, {} was not written", config.origin);
+        return Ok(());
+    }
+
+    std::fs::write(&cmd.config, &migration.text)
+        .with_context(|| format!("failed to write {}", cmd.config))?;
+    println!(
+        "✅ Migrated {} to version {}",
+        config.origin, CURRENT_VERSION
+    );
+    Ok(())
+}
+
+/// Schema version of `value` (0 if it has none), at most `current`.
+fn version(config: &ConfigSource, value: &Value, current: u64) -> Result<u64> {
+    let issue = match value.get("version") {
+        None => return Ok(0),
+        Some(version) => match version.as_u64() {
+            Some(version) if version <= current => return Ok(version),
+            Some(version) => Issue::new(
+                "version",
+                format!(
+                    "config version {} is newer than this netgen supports ({})",
+                    version, current
+                ),
+            )
+            .suggest("upgrade netgen"),
+            None => Issue::new("version", "expected a non-negative integer")
+                .suggest(format!("version: {}", current)),
+        },
+    };
+    Err(ValidationError::located(config, vec![issue]).into())
+}
+
+/// Apply the shape migrations of `steps` in order, without touching `version`.
+fn upgrade(mut value: Value, steps: &[Step]) -> (Value, Vec<Edit>) {
+    let mut edits = Vec::new();
+    for migration in steps {
+        for edit in migration(&value) {
+            apply_value(&mut value, &edit.change);
+            edits.push(edit);
+        }
+    }
+    (value, edits)
+}
+
+fn apply_value(value: &mut Value, change: &Change) {
+    let (path, key) = match change {
+        Change::Set { path, .. } | Change::Rename { path, .. } | Change::Remove { path } => {
+            match path.split_last() {
+                Some((key, parent)) => (parent, key),
+                None => return,
+            }
+        }
+    };
+    let Some(map) = parent_map(value, path) else {
+        return;
+    };
+
+    match change {
+        Change::Set { value, .. } => match map.get_mut(key) {
+            Some(existing) => *existing = value.clone(),
+            None => {
+                map.shift_insert(0, key.clone(), value.clone());
+            }
+        },
+        Change::Rename { to, .. } => {
+            if let Some(index) = map.keys().position(|k| k == key) {
+                let value = map.shift_remove(key).expect("key was just found");
+                map.shift_insert(index, to.clone(), value);
+            }
+        }
+        Change::Remove { .. } => {
+            map.shift_remove(key);
+        }
+    }
+}
+
+fn parent_map<'a>(value: &'a mut Value, path: &[String]) -> Option<&'a mut Map<String, Value>> {
+    path.iter()
+        .try_fold(value, |value, key| value.get_mut(key))?
+        .as_object_mut()
+}
+
+/// Apply `changes` to YAML text line by line, leaving everything else (comments
+/// included) untouched. `None` if a change needs more than a block-style edit.
+fn edit_yaml(text: &str, changes: &[&Change]) -> Option<String> {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    for change in changes {
+        let positions = yaml_positions(&lines.join("\n"));
+        let (path, key) = match change {
+            Change::Set { path, .. } | Change::Rename { path, .. } | Change::Remove { path } => {
+                let (key, parent) = path.split_last()?;
+                (parent.join("."), key.as_str())
+            }
+        };
+        let full = if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        };
+
+        // Block-style key on its own line: 0-based line index and indentation.
+        let located = positions.get(&full).and_then(|&(line, column)| {
+            let text = &lines[line - 1];
+            let indent = column - 1;
+            let own_line = text.get(..indent).is_some_and(|s| s.trim().is_empty())
+                && text[indent..].starts_with(key);
+            own_line.then_some((line - 1, indent))
+        });
+
+        match change {
+            Change::Set { value, .. } => {
+                let scalar = serde_yaml::to_string(value).ok()?;
+                let scalar = scalar.trim_end();
+                if scalar.contains('\n') {
+                    return None;
+                }
+
+                match located {
+                    Some((line, indent)) => {
+                        let rest = &lines[line][indent + key.len()..];
+                        let rest = rest.trim_start().strip_prefix(':')?;
+                        let comment = rest.find(" #").map(|i| &rest[i..]).unwrap_or("");
+                        if rest[..rest.len() - comment.len()].trim().is_empty() {
+                            // A nested block, not a scalar.
+                            return None;
+                        }
+                        lines[line] =
+                            format!("{}{}: {}{}", &lines[line][..indent], key, scalar, comment);
+                    }
+                    None => {
+                        // Insert before the first key of the parent mapping.
+                        let prefix = if path.is_empty() {
+                            String::new()
+                        } else {
+                            format!("{}.", path)
+                        };
+                        let &(line, column) = positions
+                            .iter()
+                            .filter(|(p, _)| {
+                                p.strip_prefix(&prefix).is_some_and(|rest| {
+                                    !rest.is_empty() && !rest.contains(['.', '['])
+                                })
+                            })
+                            .map(|(_, pos)| pos)
+                            .min()?;
+                        let indent = lines[line - 1].get(..column - 1)?;
+                        if !indent.trim().is_empty() {
+                            return None;
+                        }
+                        let new = format!("{}{}: {}", indent, key, scalar);
+                        lines.insert(line - 1, new);
+                    }
+                }
+            }
+            Change::Rename { to, .. } => {
+                let (line, indent) = located?;
+                let end = indent + key.len();
+                if !lines[line][end..].trim_start().starts_with(':') {
+                    return None;
+                }
+                lines[line].replace_range(indent..end, to);
+            }
+            Change::Remove { .. } => {
+                let (line, indent) = located?;
+                // The key, then everything indented deeper; comments and
+                // blank lines only go with it if more of its block follows.
+                let mut end = line + 1;
+                let mut next = line + 1;
+                while next < lines.len() {
+                    let trimmed = lines[next].trim_start();
+                    if trimmed.is_empty() || trimmed.starts_with('#') {
+                        next += 1;
+                        continue;
+                    }
+                    if lines[next].len() - trimmed.len() <= indent {
+                        break;
+                    }
+                    next += 1;
+                    end = next;
+                }
+                lines.drain(line..end);
+            }
+        }
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Some(text)
+}
+
+/// Apply `changes` to a TOML document with `toml_edit`, which keeps comments
+/// and formatting. `None` if a change needs more than that.
+fn edit_toml(text: &str, changes: &[&Change]) -> Option<String> {
+    let mut doc: toml_edit::DocumentMut = text.parse().ok()?;
+
+    for change in changes {
+        let (path, key) = match change {
+            Change::Set { path, .. } | Change::Rename { path, .. } | Change::Remove { path } => {
+                let (key, parent) = path.split_last()?;
+                (parent, key.as_str())
+            }
+        };
+        let table = path.iter().try_fold(doc.as_table_mut(), |table, key| {
+            table.get_mut(key)?.as_table_mut()
+        })?;
+
+        match change {
+            Change::Set { value, .. } => {
+                let mut value = toml_value(value)?;
+                if table.contains_key(key) {
+                    let existing = table.get_mut(key)?.as_value_mut()?;
+                    *value.decor_mut() = existing.decor().clone();
+                    *existing = value;
+                } else {
+                    // The new key goes first and takes over the comment
+                    // above the old first key.
+                    let first = table
+                        .iter()
+                        .find(|(_, item)| item.is_value())
+                        .map(|(k, _)| k.to_string());
+                    table.insert(key, toml_edit::Item::Value(value));
+                    table.sort_values_by(|a, _, b, _| (b.get() == key).cmp(&(a.get() == key)));
+                    if let Some(first) = first {
+                        let prefix = table.key_mut(&first)?.leaf_decor_mut().prefix().cloned();
+                        if let Some(prefix) = prefix {
+                            table.key_mut(&first)?.leaf_decor_mut().set_prefix("");
+                            table.key_mut(key)?.leaf_decor_mut().set_prefix(prefix);
+                        }
+                    }
+                }
+            }
+            Change::Rename { to, .. } => {
+                let order: Vec<String> = table.iter().map(|(k, _)| k.to_string()).collect();
+                let (old, item) = table.remove_entry(key)?;
+                let renamed =
+                    toml_edit::Key::new(to.as_str()).with_leaf_decor(old.leaf_decor().clone());
+                table.insert_formatted(&renamed, item);
+                let rank = |name: &str| {
+                    let name = if name == to { key } else { name };
+                    order.iter().position(|k| k == name)
+                };
+                table.sort_values_by(|a, _, b, _| rank(a.get()).cmp(&rank(b.get())));
+            }
+            Change::Remove { .. } => {
+                table.remove(key)?;
+            }
+        }
+    }
+
+    Some(doc.to_string())
+}
+
+/// TOML value for a scalar JSON value.
+fn toml_value(value: &Value) -> Option<toml_edit::Value> {
+    match value {
+        Value::Bool(b) => Some((*b).into()),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Some(i.into()),
+            None => n.as_f64().map(Into::into),
+        },
+        Value::String(s) => Some(s.as_str().into()),
+        _ => None,
+    }
+}
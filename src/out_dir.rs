@@ -0,0 +1,84 @@
+// src/out_dir.rs
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// Directory written into every generated project; it holds the lockfile.
+/// A directory containing it may be overwritten without `--force`.
+pub const MARKER_DIR: &str = ".netgen";
+
+/// What to do when the output directory holds files not generated by netgen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Fail with an error.
+    #[default]
+    Refuse,
+    /// Write into the directory anyway.
+    Force,
+    /// Move the directory aside and generate into a fresh one.
+    Backup,
+}
+
+/// If `out_dir` exists, is not empty and has no netgen marker,
+/// return one of its entries as an example of foreign content.
+pub fn foreign_entry(out_dir: &Path) -> Result<Option<PathBuf>> {
+    if !out_dir.exists() {
+        return Ok(None);
+    }
+    if !out_dir.is_dir() {
+        return Ok(Some(out_dir.to_path_buf()));
+    }
+    if out_dir.join(MARKER_DIR).exists() {
+        return Ok(None);
+    }
+
+    let mut entries = std::fs::read_dir(out_dir)
+        .with_context(|| format!("failed to read {}", out_dir.display()))?;
+    match entries.next() {
+        Some(entry) => Ok(Some(entry?.path())),
+        None => Ok(None),
+    }
+}
+
+/// Make sure generating into `out_dir` does not clobber foreign content.
+/// Returns the backup location if the directory was moved aside.
+pub fn prepare_out_dir(out_dir: &Path, policy: OverwritePolicy) -> Result<Option<PathBuf>> {
+    let Some(entry) = foreign_entry(out_dir)? else {
+        return Ok(None);
+    };
+
+    match policy {
+        OverwritePolicy::Refuse => bail!(
+            "refusing to generate into {}: it contains files not generated by netgen (e.g. {}). \
+             Use --force to write into it anyway, or --backup to move it aside first",
+            out_dir.display(),
+            entry.display()
+        ),
+        OverwritePolicy::Force => Ok(None),
+        OverwritePolicy::Backup => {
+            let backup = backup_path(out_dir);
+            std::fs::rename(out_dir, &backup).with_context(|| {
+                format!(
+                    "failed to move {} to {}",
+                    out_dir.display(),
+                    backup.display()
+                )
+            })?;
+            Ok(Some(backup))
+        }
+    }
+}
+
+/// First free path of the form `<dir>.bak`, `<dir>.bak.1`, `<dir>.bak.2`, ...
+fn backup_path(out_dir: &Path) -> PathBuf {
+    let base = out_dir.as_os_str().to_string_lossy().into_owned();
+    let base = base.trim_end_matches('/');
+
+    let mut candidate = PathBuf::from(format!("{}.bak", base));
+    let mut n = 1;
+    while candidate.exists() {
+        candidate = PathBuf::from(format!("{}.bak.{}", base, n));
+        n += 1;
+    }
+    candidate
+}
@@ -0,0 +1,200 @@
+// src/post_generate.rs
+//! Optional steps run on a generated project: rustfmt and `cargo check`.
+
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::generator::{FileTree, GenerateArgs, OutputFile};
+use crate::templates::TemplateSet;
+
+/// Step run after a project is generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PostStep {
+    /// Format the generated Rust files with rustfmt.
+    Fmt,
+    /// Type-check the generated project with `cargo check`.
+    Check,
+}
+
+impl PostStep {
+    /// Command shown in messages.
+    pub fn command(&self) -> &'static str {
+        match self {
+            PostStep::Fmt => "rustfmt",
+            PostStep::Check => "cargo check",
+        }
+    }
+}
+
+/// Steps requested on the command line (`--fmt`, `--check`) or in the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PostSteps {
+    pub fmt: bool,
+    pub check: bool,
+}
+
+impl PostSteps {
+    pub fn new(args: &GenerateArgs, configured: &[PostStep]) -> Self {
+        Self {
+            fmt: args.fmt || configured.contains(&PostStep::Fmt),
+            check: args.check || configured.contains(&PostStep::Check),
+        }
+    }
+}
+
+/// Generated file and the template it was rendered from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOrigin {
+    /// Path as it appears in compiler output (relative to where the step ran).
+    pub path: String,
+    /// Template override path, or the embedded template name.
+    pub template: String,
+}
+
+/// Where each of `files` comes from. `prefix` is prepended to the output paths
+/// (e.g. `api/` for a workspace member).
+pub fn file_origins(
+    files: &[OutputFile],
+    templates: &TemplateSet,
+    prefix: &str,
+) -> Vec<FileOrigin> {
+    files
+        .iter()
+        .map(|file| FileOrigin {
+            path: format!("{}{}", prefix, file.path),
+            template: templates.source_of(file.template),
+        })
+        .collect()
+}
+
+/// A post-generation step that failed.
+#[derive(Debug, Clone)]
+pub struct PostGenerateError {
+    pub step: PostStep,
+    /// Config the project was generated from, if known.
+    pub config: Option<String>,
+    /// Output of the failing command.
+    pub output: String,
+    /// Generated files mentioned in the output.
+    pub files: Vec<FileOrigin>,
+}
+
+impl PostGenerateError {
+    fn new(step: PostStep, output: String, origins: &[FileOrigin]) -> Self {
+        let mentioned: Vec<_> = origins
+            .iter()
+            .filter(|origin| output.contains(&origin.path))
+            .cloned()
+            .collect();
+
+        Self {
+            step,
+            config: None,
+            output,
+            files: if mentioned.is_empty() {
+                origins.to_vec()
+            } else {
+                mentioned
+            },
+        }
+    }
+}
+
+impl fmt::Display for PostGenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.config {
+            Some(config) => writeln!(
+                f,
+                "`{}` failed on the project generated from {}:",
+                self.step.command(),
+                config
+            )?,
+            None => writeln!(
+                f,
+                "`{}` failed on the generated project:",
+                self.step.command()
+            )?,
+        }
+        for line in self.output.trim_end().lines() {
+            writeln!(f, "  {}", line)?;
+        }
+        writeln!(f, "The generated files involved come from these templates:")?;
+        for origin in &self.files {
+            writeln!(f, "  {} <- {}", origin.path, origin.template)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PostGenerateError {}
+
+/// Record `config` as the source of a [`PostGenerateError`]; other errors pass through.
+pub fn with_config(err: anyhow::Error, config: &str) -> anyhow::Error {
+    match err.downcast::<PostGenerateError>() {
+        Ok(mut err) => {
+            err.config = Some(config.to_string());
+            err.into()
+        }
+        Err(err) => err,
+    }
+}
+
+/// Format every `.rs` file of `files` in memory with rustfmt.
+pub fn format_files(files: &mut FileTree, origins: &[FileOrigin]) -> Result<()> {
+    for (path, contents) in files.iter_mut() {
+        if !path.ends_with(".rs") {
+            continue;
+        }
+
+        let mut child = Command::new("rustfmt")
+            .args(["--edition", "2021", "--emit", "stdout"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("failed to run rustfmt (is it installed?)")?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(contents.as_bytes())?;
+        let output = child.wait_with_output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).replace("<stdin>", path);
+            let origin: Vec<_> = origins
+                .iter()
+                .filter(|origin| origin.path.ends_with(path.as_str()))
+                .cloned()
+                .collect();
+            return Err(PostGenerateError::new(PostStep::Fmt, stderr, &origin).into());
+        }
+        *contents = String::from_utf8(output.stdout).context("rustfmt produced invalid UTF-8")?;
+    }
+
+    Ok(())
+}
+
+/// Run `cargo check` in `dir`.
+pub fn check_project(dir: &Path, origins: &[FileOrigin]) -> Result<()> {
+    let output = Command::new("cargo")
+        .args(["check", "--quiet", "--message-format", "short"])
+        .current_dir(dir)
+        .output()
+        .context("failed to run cargo check")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(PostGenerateError::new(PostStep::Check, stderr, origins).into());
+    }
+
+    println!("🔎 cargo check passed for {}", dir.to_string_lossy());
+    Ok(())
+}
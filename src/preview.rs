@@ -0,0 +1,113 @@
+// src/preview.rs
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use similar::TextDiff;
+
+use crate::generator::FileTree;
+
+/// What writing a rendered file would do to the file on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Create,
+    Update,
+    Unchanged,
+}
+
+impl FileStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileStatus::Create => "create",
+            FileStatus::Update => "update",
+            FileStatus::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Planned change for one output file.
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    /// Path relative to the project root.
+    pub path: PathBuf,
+    pub status: FileStatus,
+    /// Current contents on disk (if the file exists).
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// Compare rendered files against what is already in `out_dir`.
+pub fn plan_changes(out_dir: &Path, files: &FileTree) -> Result<Vec<FileChange>> {
+    let mut changes = Vec::with_capacity(files.len());
+
+    for (path, new) in files {
+        let on_disk = out_dir.join(path);
+        let old = if on_disk.is_file() {
+            Some(std::fs::read_to_string(&on_disk)?)
+        } else {
+            None
+        };
+
+        let status = match &old {
+            None => FileStatus::Create,
+            Some(old) if old == new => FileStatus::Unchanged,
+            Some(_) => FileStatus::Update,
+        };
+
+        changes.push(FileChange {
+            path: PathBuf::from(path),
+            status,
+            old,
+            new: new.clone(),
+        });
+    }
+
+    Ok(changes)
+}
+
+/// Print the list of files that would be created or changed.
+pub fn print_dry_run(out_dir: &Path, changes: &[FileChange]) {
+    println!(
+        "🔍 Dry run, nothing written to {}",
+        out_dir.to_string_lossy()
+    );
+    for change in changes {
+        println!(
+            "  {:<9} {}",
+            change.status.label(),
+            change.path.to_string_lossy()
+        );
+    }
+}
+
+/// Unified diff between the file on disk and the rendered file.
+/// Returns `None` for unchanged files.
+pub fn unified_diff(change: &FileChange) -> Option<String> {
+    if change.status == FileStatus::Unchanged {
+        return None;
+    }
+
+    let path = change.path.to_string_lossy();
+    let old_header = match change.status {
+        FileStatus::Create => "/dev/null".to_string(),
+        _ => format!("a/{}", path),
+    };
+    let new_header = format!("b/{}", path);
+
+    let old = change.old.as_deref().unwrap_or("");
+    let diff = TextDiff::from_lines(old, change.new.as_str())
+        .unified_diff()
+        .context_radius(3)
+        .header(&old_header, &new_header)
+        .to_string();
+
+    Some(diff)
+}
+
+/// Print a unified diff for every file that would change.
+pub fn print_diff(changes: &[FileChange]) {
+    for change in changes {
+        if let Some(diff) = unified_diff(change) {
+            print!("{}", diff);
+        }
+    }
+}
@@ -1,27 +1,281 @@
 // src/read_mode.rs
-use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+
+use anyhow::{bail, Result};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::validate::{check_positive, Issue};
+
+/// How the generated TCP servers implement the read mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// A hand-written read loop per read mode.
+    #[default]
+    Loop,
+    /// A `tokio_util::codec` `Decoder`/`Encoder` driven through `Framed`.
+    Codec,
+}
 
 /// Common enum for describing read mode in YAML.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum YamlReadMode {
+    /// Newline-delimited lines, read as raw bytes.
     #[serde(rename = "lines")]
-    Lines { max_line_len: Option<usize> },
+    Lines {
+        /// Maximum line length in bytes, enforced while reading.
+        max_line_len: Option<usize>,
+        /// Close connections that send a line that is not valid UTF-8.
+        #[serde(default)]
+        validate_utf8: bool,
+        /// What to do with longer lines (default: truncate).
+        on_oversize: Option<OnOversize>,
+        /// Reply sent with `on_oversize: reply_error`, written like `delim`
+        /// (default: `frame too large\n`).
+        error_frame: Option<ByteString>,
+    },
+    /// Frames of a fixed size.
     #[serde(rename = "fixed_size")]
-    FixedSize { frame_size: usize },
+    FixedSize {
+        /// Frame size in bytes.
+        frame_size: usize,
+    },
+    /// Frames terminated by a delimiter.
     #[serde(rename = "delimited")]
     Delimited {
-        /// Delimiter byte (0-255), e.g. 10 == '\n'
-        delim: u8,
+        /// Delimiter: a byte (`10`), an escaped string (`'\r\n'`, `'END\n'`),
+        /// a quoted hex string (`"0x0d0a"`) or an array of bytes (`[13, 10]`).
+        delim: ByteString,
+        /// Pass frames on without their delimiter.
+        #[serde(default)]
+        strip_delim: bool,
+        /// Maximum frame length in bytes.
         max_len: Option<usize>,
+        /// What to do with longer frames (default: truncate).
+        on_oversize: Option<OnOversize>,
+        /// Reply sent with `on_oversize: reply_error`, written like `delim`
+        /// (default: `frame too large\n`).
+        error_frame: Option<ByteString>,
     },
+    /// Frames preceded by a binary length header. The header layout options
+    /// follow tokio-util's `LengthDelimitedCodec`.
     #[serde(rename = "length_prefixed")]
     LengthPrefixed {
-        /// Usually 1, 2, or 4 bytes for length
+        /// Width of the length header in bytes (1 to 8).
+        #[schemars(range(min = 1, max = 8))]
         len_bytes: usize,
+        /// Byte order of the length header (true = big endian).
         big_endian: bool,
+        /// The length header is a two's complement integer; negative lengths
+        /// close the connection.
+        #[serde(default)]
+        signed: bool,
+        /// Header bytes before the length field.
+        #[serde(default)]
+        length_field_offset: usize,
+        /// Added to the length field to get the number of bytes that follow
+        /// the skipped ones.
+        #[serde(default)]
+        length_adjustment: i64,
+        /// Bytes dropped from the start of every frame before it is passed on
+        /// (defaults to the whole header).
+        num_skip: Option<usize>,
+        /// The length field counts the whole frame, header included.
+        #[serde(default)]
+        length_includes_header: bool,
+        /// Maximum frame length in bytes, not counting the skipped ones
+        /// (default: 8 MiB).
         max_len: Option<usize>,
+        /// What to do with longer frames (default: close).
+        on_oversize: Option<OnOversize>,
+        /// Reply sent with `on_oversize: reply_error`, written like `delim`
+        /// (default: `frame too large\n`).
+        error_frame: Option<ByteString>,
     },
+    /// Frames preceded by an unsigned LEB128 varint length, as in protobuf
+    /// length-delimited streams.
+    #[serde(rename = "varint_prefixed")]
+    VarintPrefixed {
+        /// Maximum frame length in bytes (default: 8 MiB).
+        max_len: Option<usize>,
+        /// Accept non-minimal encodings such as `0x80 0x00` for 0. Lengths
+        /// longer than 10 bytes or above 64 bits are always rejected.
+        #[serde(default)]
+        allow_overlong: bool,
+        /// What to do with longer frames (default: close).
+        on_oversize: Option<OnOversize>,
+        /// Reply sent with `on_oversize: reply_error`, written like `delim`
+        /// (default: `frame too large\n`).
+        error_frame: Option<ByteString>,
+    },
+}
+
+/// `max_len` of the modes that read a frame length from the peer, so that no
+/// header makes the servers allocate more. Same as tokio-util's
+/// `LengthDelimitedCodec`.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
+/// What the generated servers do with a frame longer than its maximum length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnOversize {
+    /// Pass on the first `max_len` bytes and drop the rest.
+    Truncate,
+    /// Drop the whole frame and keep reading.
+    DropFrame,
+    /// Close the connection.
+    Close,
+    /// Drop the frame and send the `error_frame` back.
+    ReplyError,
+}
+
+impl OnOversize {
+    /// How the generated servers log it, e.g. "frame ... exceeded max_len, dropping it".
+    pub fn action(self) -> &'static str {
+        match self {
+            OnOversize::Truncate => "truncating",
+            OnOversize::DropFrame => "dropping it",
+            OnOversize::Close => "closing",
+            OnOversize::ReplyError => "replying with an error",
+        }
+    }
+}
+
+impl YamlReadMode {
+    /// Semantic checks; `path` is the key path of the read mode in the config.
+    pub fn validate(&self, path: &str) -> Vec<Issue> {
+        let mut issues = Vec::new();
+
+        match self {
+            YamlReadMode::Lines { max_line_len, .. } => {
+                if let Some(len) = max_line_len {
+                    issues.extend(check_positive(
+                        &format!("{}.max_line_len", path),
+                        *len,
+                        8192,
+                    ));
+                }
+            }
+            YamlReadMode::FixedSize { frame_size } => {
+                issues.extend(check_positive(
+                    &format!("{}.frame_size", path),
+                    *frame_size,
+                    1024,
+                ));
+            }
+            YamlReadMode::Delimited { delim, max_len, .. } => {
+                if delim.0.is_empty() {
+                    issues.push(
+                        Issue::new(format!("{}.delim", path), "delimiter must not be empty")
+                            .suggest("delim: \"\\n\""),
+                    );
+                }
+                if let Some(len) = max_len {
+                    issues.extend(check_positive(&format!("{}.max_len", path), *len, 65535));
+                }
+            }
+            YamlReadMode::LengthPrefixed {
+                len_bytes,
+                big_endian,
+                signed,
+                max_len,
+                ..
+            } => {
+                if let Err(err) = LengthField::new(*len_bytes, *signed, *big_endian) {
+                    let nearest = len_bytes.clamp(&1, &LengthField::MAX_BYTES);
+                    issues.push(
+                        Issue::new(format!("{}.len_bytes", path), err.to_string())
+                            .suggest(format!("len_bytes: {}", nearest)),
+                    );
+                }
+                if let Some(len) = max_len {
+                    issues.extend(check_positive(&format!("{}.max_len", path), *len, 65535));
+                }
+            }
+            YamlReadMode::VarintPrefixed { max_len, .. } => {
+                if let Some(len) = max_len {
+                    issues.extend(check_positive(&format!("{}.max_len", path), *len, 65535));
+                }
+            }
+        }
+        issues.extend(self.validate_oversize(path));
+
+        issues
+    }
+
+    /// Max length key, `on_oversize` and `error_frame` of modes that limit
+    /// frame lengths.
+    fn oversize(&self) -> Option<(&'static str, Option<usize>, Option<OnOversize>, bool)> {
+        match self {
+            YamlReadMode::Lines {
+                max_line_len,
+                on_oversize,
+                error_frame,
+                ..
+            } => Some((
+                "max_line_len",
+                *max_line_len,
+                *on_oversize,
+                error_frame.is_some(),
+            )),
+            YamlReadMode::FixedSize { .. } => None,
+            YamlReadMode::Delimited {
+                max_len,
+                on_oversize,
+                error_frame,
+                ..
+            }
+            | YamlReadMode::LengthPrefixed {
+                max_len,
+                on_oversize,
+                error_frame,
+                ..
+            }
+            | YamlReadMode::VarintPrefixed {
+                max_len,
+                on_oversize,
+                error_frame,
+                ..
+            } => Some(("max_len", *max_len, *on_oversize, error_frame.is_some())),
+        }
+    }
+
+    fn validate_oversize(&self, path: &str) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let Some((max_key, max_len, on_oversize, has_error_frame)) = self.oversize() else {
+            return issues;
+        };
+
+        // Lengths read from the peer are always capped.
+        let capped = matches!(
+            self,
+            YamlReadMode::LengthPrefixed { .. } | YamlReadMode::VarintPrefixed { .. }
+        );
+        if on_oversize.is_some() && max_len.is_none() && !capped {
+            issues.push(
+                Issue::new(
+                    format!("{}.on_oversize", path),
+                    format!("on_oversize has no effect without {}", max_key),
+                )
+                .suggest(format!("{}: 65535", max_key)),
+            );
+        }
+        if has_error_frame && on_oversize != Some(OnOversize::ReplyError) {
+            issues.push(
+                Issue::new(
+                    format!("{}.error_frame", path),
+                    "error_frame is only sent with on_oversize: reply_error",
+                )
+                .suggest("on_oversize: reply_error"),
+            );
+        }
+
+        issues
+    }
 }
 
 /// Context that goes directly into the template (handlebars).
@@ -30,25 +284,80 @@ pub enum YamlReadMode {
 pub struct ReadModeTemplateCtx {
     // lines mode
     pub max_line_len: Option<usize>,
+    pub lines_validate_utf8: bool,
 
     // fixed_size mode
     pub frame_size: Option<usize>,
 
     // delimited mode
-    pub delim_byte: Option<u8>,
+    /// Rust byte string literal, e.g. `b"\r\n"`.
+    pub delim_literal: Option<String>,
+    pub delim_strip: bool,
     pub delim_max_len: Option<usize>,
 
     // length_prefixed mode
-    pub lp_len_bytes: Option<usize>,
-    pub lp_big_endian: Option<bool>,
+    pub length_header: Option<LengthHeaderTemplateCtx>,
     pub lp_max_len: Option<usize>,
-    pub lp_parse_len_code: String,
+
+    // varint_prefixed mode
+    pub varint_max_len: Option<usize>,
+    pub varint_allow_overlong: bool,
 
     // mode flags
     pub is_lines: bool,
     pub is_fixed_size: bool,
     pub is_delimited: bool,
     pub is_length_prefixed: bool,
+    pub is_varint_prefixed: bool,
+
+    // oversized frames, see `OnOversize`
+    pub oversize: OversizeTemplateCtx,
+
+    /// Generate a `FrameCodec` and drive connections through `Framed`.
+    pub use_codec: bool,
+
+    /// Framing of replies, `None` to write them as they are.
+    pub response: Option<ResponseTemplateCtx>,
+}
+
+/// [`OnOversize`] as used by the templates.
+#[derive(Debug, Default, Serialize)]
+pub struct OversizeTemplateCtx {
+    pub truncate: bool,
+    pub drop_frame: bool,
+    pub close: bool,
+    pub reply_error: bool,
+    /// See [`OnOversize::action`].
+    pub action: &'static str,
+    /// Rust byte string literal of the error frame.
+    pub error_frame_literal: String,
+    /// The rest of an oversized frame is still unread when its length is
+    /// known, so everything but `close` has to read past it.
+    pub discard_unread: bool,
+}
+
+impl OversizeTemplateCtx {
+    /// Nothing is set without a `max_len`.
+    fn new(
+        max_len: Option<usize>,
+        policy: OnOversize,
+        error_frame: Option<ByteString>,
+        length_prefixed: bool,
+    ) -> Self {
+        if max_len.is_none() {
+            return OversizeTemplateCtx::default();
+        }
+        let error_frame = error_frame.unwrap_or_else(|| ByteString(b"frame too large\n".to_vec()));
+        OversizeTemplateCtx {
+            discard_unread: length_prefixed && policy != OnOversize::Close,
+            truncate: policy == OnOversize::Truncate,
+            drop_frame: policy == OnOversize::DropFrame,
+            close: policy == OnOversize::Close,
+            reply_error: policy == OnOversize::ReplyError,
+            action: policy.action(),
+            error_frame_literal: error_frame.rust_literal(),
+        }
+    }
 }
 
 impl From<YamlReadMode> for ReadModeTemplateCtx {
@@ -56,29 +365,96 @@ impl From<YamlReadMode> for ReadModeTemplateCtx {
         let mut ctx = ReadModeTemplateCtx::default();
 
         match mode {
-            YamlReadMode::Lines { max_line_len } => {
+            YamlReadMode::Lines {
+                max_line_len,
+                validate_utf8,
+                on_oversize,
+                error_frame,
+            } => {
                 ctx.is_lines = true;
                 ctx.max_line_len = max_line_len;
+                ctx.lines_validate_utf8 = validate_utf8;
+                ctx.oversize = OversizeTemplateCtx::new(
+                    max_line_len,
+                    on_oversize.unwrap_or(OnOversize::Truncate),
+                    error_frame,
+                    false,
+                );
             }
             YamlReadMode::FixedSize { frame_size } => {
                 ctx.is_fixed_size = true;
                 ctx.frame_size = Some(frame_size);
             }
-            YamlReadMode::Delimited { delim, max_len } => {
+            YamlReadMode::Delimited {
+                delim,
+                strip_delim,
+                max_len,
+                on_oversize,
+                error_frame,
+            } => {
                 ctx.is_delimited = true;
-                ctx.delim_byte = Some(delim);
+                ctx.oversize = OversizeTemplateCtx::new(
+                    max_len,
+                    on_oversize.unwrap_or(OnOversize::Truncate),
+                    error_frame,
+                    false,
+                );
+                ctx.delim_literal = Some(delim.rust_literal());
+                ctx.delim_strip = strip_delim;
                 ctx.delim_max_len = max_len;
             }
             YamlReadMode::LengthPrefixed {
                 len_bytes,
                 big_endian,
+                signed,
+                length_field_offset,
+                length_adjustment,
+                num_skip,
+                length_includes_header,
                 max_len,
+                on_oversize,
+                error_frame,
             } => {
+                let max_len = Some(max_len.unwrap_or(DEFAULT_MAX_FRAME_LEN));
                 ctx.is_length_prefixed = true;
-                ctx.lp_len_bytes = Some(len_bytes);
-                ctx.lp_big_endian = Some(big_endian);
+                ctx.oversize = OversizeTemplateCtx::new(
+                    max_len,
+                    on_oversize.unwrap_or(OnOversize::Close),
+                    error_frame,
+                    true,
+                );
+                // Out-of-range widths are rejected by `validate`.
+                ctx.length_header =
+                    LengthField::new(len_bytes, signed, big_endian)
+                        .ok()
+                        .map(|field| {
+                            LengthHeader {
+                                field,
+                                offset: length_field_offset,
+                                adjustment: length_adjustment,
+                                num_skip,
+                                includes_header: length_includes_header,
+                            }
+                            .into()
+                        });
                 ctx.lp_max_len = max_len;
-                ctx.lp_parse_len_code = lp_parse_len_snippet(len_bytes, big_endian);
+            }
+            YamlReadMode::VarintPrefixed {
+                max_len,
+                allow_overlong,
+                on_oversize,
+                error_frame,
+            } => {
+                let max_len = Some(max_len.unwrap_or(DEFAULT_MAX_FRAME_LEN));
+                ctx.is_varint_prefixed = true;
+                ctx.oversize = OversizeTemplateCtx::new(
+                    max_len,
+                    on_oversize.unwrap_or(OnOversize::Close),
+                    error_frame,
+                    true,
+                );
+                ctx.varint_max_len = max_len;
+                ctx.varint_allow_overlong = allow_overlong;
             }
         }
 
@@ -86,17 +462,493 @@ impl From<YamlReadMode> for ReadModeTemplateCtx {
     }
 }
 
-fn lp_parse_len_snippet(len_bytes: usize, big_endian: bool) -> String {
-    match (len_bytes, big_endian) {
-        (1, _) => "let frame_len: usize = len_buf[0] as usize;".to_string(),
-        (2, true) =>
-            "let frame_len: usize = u16::from_be_bytes([len_buf[0], len_buf[1]]) as usize;".to_string(),
-        (2, false) =>
-            "let frame_len: usize = u16::from_le_bytes([len_buf[0], len_buf[1]]) as usize;".to_string(),
-        (4, true) =>
-            "let frame_len: usize = u32::from_be_bytes([len_buf[0], len_buf[1], len_buf[2], len_buf[3]]) as usize;".to_string(),
-        (4, false) =>
-            "let frame_len: usize = u32::from_le_bytes([len_buf[0], len_buf[1], len_buf[2], len_buf[3]]) as usize;".to_string(),
-        _ => panic!("len_bytes must be 1, 2 or 4"),
+/// How the generated TCP servers frame their replies, independently of
+/// `read_mode`: `same`, `raw` or a read mode of its own.
+#[derive(Debug, Default)]
+pub enum YamlResponse {
+    /// Frame replies the way incoming frames are framed.
+    Same,
+    /// Write the bytes of every reply as they are.
+    #[default]
+    Raw,
+    /// Frame replies with another read mode.
+    ReadMode(YamlReadMode),
+}
+
+impl YamlResponse {
+    /// Semantic checks; `path` is the key path of the response in the config.
+    pub fn validate(&self, path: &str, read_mode: &YamlReadMode) -> Vec<Issue> {
+        let mode = match self {
+            YamlResponse::Raw => return Vec::new(),
+            YamlResponse::Same => read_mode,
+            YamlResponse::ReadMode(mode) => mode,
+        };
+
+        let mut issues = Vec::new();
+        if let YamlResponse::ReadMode(mode) = self {
+            issues.extend(mode.validate(path));
+            if let Some((max_key, Some(_), _, _)) = mode.oversize() {
+                issues.push(Issue::new(
+                    format!("{}.{}", path, max_key),
+                    format!("{} has no effect on replies", max_key),
+                ));
+            }
+        }
+        if let YamlReadMode::LengthPrefixed {
+            len_bytes,
+            length_field_offset,
+            num_skip: Some(num_skip),
+            ..
+        } = mode
+        {
+            let header_len = length_field_offset + len_bytes;
+            if *num_skip < header_len {
+                let issue = Issue::new(
+                    path,
+                    format!(
+                        "length_prefixed replies need num_skip to cover the {}-byte header",
+                        header_len
+                    ),
+                );
+                issues.push(match self {
+                    YamlResponse::Same => issue.suggest("response: raw"),
+                    _ => issue.suggest(format!("num_skip: {}", header_len)),
+                });
+            }
+        }
+        issues
+    }
+
+    /// Context of the reply encoder, `None` for raw replies.
+    pub fn template_ctx(&self, read_mode: &YamlReadMode) -> Option<ResponseTemplateCtx> {
+        match self {
+            YamlResponse::Raw => None,
+            YamlResponse::Same => Some(read_mode.into()),
+            YamlResponse::ReadMode(mode) => Some(mode.into()),
+        }
+    }
+}
+
+impl Serialize for YamlResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            YamlResponse::Same => serializer.serialize_str("same"),
+            YamlResponse::Raw => serializer.serialize_str("raw"),
+            YamlResponse::ReadMode(mode) => mode.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for YamlResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct YamlResponseVisitor;
+
+        impl<'de> Visitor<'de> for YamlResponseVisitor {
+            type Value = YamlResponse;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("`same`, `raw` or a read mode")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<YamlResponse, E> {
+                match value {
+                    "same" => Ok(YamlResponse::Same),
+                    "raw" => Ok(YamlResponse::Raw),
+                    _ => Err(E::unknown_variant(value, &["same", "raw"])),
+                }
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<YamlResponse, A::Error> {
+                YamlReadMode::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(YamlResponse::ReadMode)
+            }
+        }
+
+        deserializer.deserialize_any(YamlResponseVisitor)
+    }
+}
+
+impl JsonSchema for YamlResponse {
+    fn schema_name() -> Cow<'static, str> {
+        "YamlResponse".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let read_mode = generator.subschema_for::<YamlReadMode>();
+        json_schema!({
+            "description": "`same` frames replies like incoming frames, `raw` (the default) writes them as they are, and a read mode frames them that way.",
+            "oneOf": [
+                { "type": "string", "enum": ["same", "raw"] },
+                read_mode
+            ]
+        })
+    }
+}
+
+/// [`YamlResponse`] as used by the `read_mode/response` partial. Only the
+/// framing of replies matters, so lines are delimited by `\n`.
+#[derive(Debug, Default, Serialize)]
+pub struct ResponseTemplateCtx {
+    /// Rust byte string literal ending every reply (lines, delimited).
+    pub terminator_literal: Option<String>,
+    /// Replies are padded to a multiple of it (fixed_size).
+    pub frame_size: Option<usize>,
+    /// Header in front of every reply (length_prefixed).
+    pub length_header: Option<LengthHeaderTemplateCtx>,
+    /// Replies are preceded by a LEB128 varint length (varint_prefixed).
+    pub varint: bool,
+}
+
+impl From<&YamlReadMode> for ResponseTemplateCtx {
+    fn from(mode: &YamlReadMode) -> Self {
+        let mut ctx = ResponseTemplateCtx::default();
+
+        match mode {
+            YamlReadMode::Lines { .. } => {
+                ctx.terminator_literal = Some(ByteString(b"\n".to_vec()).rust_literal());
+            }
+            YamlReadMode::FixedSize { frame_size } => ctx.frame_size = Some(*frame_size),
+            YamlReadMode::Delimited { delim, .. } => {
+                ctx.terminator_literal = Some(delim.rust_literal());
+            }
+            YamlReadMode::LengthPrefixed {
+                len_bytes,
+                big_endian,
+                signed,
+                length_field_offset,
+                length_adjustment,
+                num_skip,
+                length_includes_header,
+                ..
+            } => {
+                // Out-of-range widths are rejected by `validate`.
+                ctx.length_header =
+                    LengthField::new(*len_bytes, *signed, *big_endian)
+                        .ok()
+                        .map(|field| {
+                            LengthHeader {
+                                field,
+                                offset: *length_field_offset,
+                                adjustment: *length_adjustment,
+                                num_skip: *num_skip,
+                                includes_header: *length_includes_header,
+                            }
+                            .into()
+                        });
+            }
+            YamlReadMode::VarintPrefixed { .. } => ctx.varint = true,
+        }
+
+        ctx
+    }
+}
+
+/// Bytes written in a config: a delimiter, an error frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteString(pub Vec<u8>);
+
+impl ByteString {
+    /// Parse bytes written as text: `0x` followed by hex digits, or a
+    /// string with `\n`, `\r`, `\t`, `\0`, `\\` and `\xNN` escapes.
+    pub fn parse(text: &str) -> Result<Self> {
+        if let Some(hex) = text.strip_prefix("0x") {
+            if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                bail!(
+                    "`{}` is not a hex string; use an even number of hex digits after 0x",
+                    text
+                );
+            }
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("checked hex digits"))
+                .collect();
+            return Ok(Self(bytes));
+        }
+
+        let mut bytes = Vec::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(i) = rest.find('\\') {
+            bytes.extend_from_slice(&rest.as_bytes()[..i]);
+            let escape = &rest[i + 1..];
+            let (byte, len) = match escape.chars().next() {
+                Some('n') => (b'\n', 1),
+                Some('r') => (b'\r', 1),
+                Some('t') => (b'\t', 1),
+                Some('0') => (0, 1),
+                Some('\\') => (b'\\', 1),
+                Some('x') => match escape
+                    .get(1..3)
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => (byte, 3),
+                    None => bail!("`\\x` in `{}` must be followed by two hex digits", text),
+                },
+                _ => bail!(
+                    "unknown escape in `{}`; use \\n, \\r, \\t, \\0, \\\\ or \\xNN",
+                    text
+                ),
+            };
+            bytes.push(byte);
+            rest = &escape[len..];
+        }
+        bytes.extend_from_slice(rest.as_bytes());
+        Ok(Self(bytes))
+    }
+
+    /// Escaped form accepted by [`ByteString::parse`] (without quotes).
+    pub fn escaped(&self) -> String {
+        let mut out = String::new();
+        for &byte in &self.0 {
+            match byte {
+                b'\n' => out.push_str("\\n"),
+                b'\r' => out.push_str("\\r"),
+                b'\t' => out.push_str("\\t"),
+                0 => out.push_str("\\0"),
+                b'\\' => out.push_str("\\\\"),
+                b' '..=b'~' => out.push(byte as char),
+                _ => out.push_str(&format!("\\x{:02x}", byte)),
+            }
+        }
+        // Keep text starting with `0x` from reading as hex.
+        if out.starts_with("0x") {
+            out.replace_range(..1, "\\x30");
+        }
+        out
+    }
+
+    /// Rust byte string literal for the generated code, e.g. `b"\r\n"`.
+    pub fn rust_literal(&self) -> String {
+        format!("b\"{}\"", self.escaped().replace('"', "\\\""))
+    }
+}
+
+impl Serialize for ByteString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.escaped())
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ByteStringVisitor;
+
+        impl<'de> Visitor<'de> for ByteStringVisitor {
+            type Value = ByteString;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a byte (0-255), an escaped or 0x hex string, or an array of bytes")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<ByteString, E> {
+                u8::try_from(value)
+                    .map(|byte| ByteString(vec![byte]))
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<ByteString, E> {
+                u8::try_from(value)
+                    .map(|byte| ByteString(vec![byte]))
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<ByteString, E> {
+                ByteString::parse(value).map_err(|err| E::custom(err))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteString, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                Ok(ByteString(bytes))
+            }
+        }
+
+        deserializer.deserialize_any(ByteStringVisitor)
+    }
+}
+
+impl JsonSchema for ByteString {
+    fn schema_name() -> Cow<'static, str> {
+        "ByteString".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A byte, an escaped string (`\\r\\n`), a hex string (`0x0d0a`) or an array of bytes.",
+            "oneOf": [
+                { "type": "integer", "minimum": 0, "maximum": 255 },
+                { "type": "string", "minLength": 1 },
+                {
+                    "type": "array",
+                    "items": { "type": "integer", "minimum": 0, "maximum": 255 },
+                    "minItems": 1
+                }
+            ]
+        })
+    }
+}
+
+/// Binary length field in front of every `length_prefixed` frame.
+///
+/// The generated decoder (the `read_mode/length_field` partial) reads the
+/// field into the smallest Rust integer that holds it, widening 3, 5, 6 and
+/// 7-byte fields and sign-extending signed ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthField {
+    /// Width in bytes, 1 to [`LengthField::MAX_BYTES`].
+    pub bytes: usize,
+    /// Two's complement instead of unsigned.
+    pub signed: bool,
+    pub big_endian: bool,
+}
+
+impl LengthField {
+    pub const MAX_BYTES: usize = 8;
+
+    pub fn new(bytes: usize, signed: bool, big_endian: bool) -> Result<Self> {
+        if !(1..=Self::MAX_BYTES).contains(&bytes) {
+            bail!(
+                "len_bytes must be between 1 and {}, got {}",
+                Self::MAX_BYTES,
+                bytes
+            );
+        }
+        Ok(Self {
+            bytes,
+            signed,
+            big_endian,
+        })
+    }
+
+    /// Largest length the field holds.
+    pub fn max_value(&self) -> u64 {
+        let bits = self.bytes * 8 - usize::from(self.signed);
+        u64::MAX >> (64 - bits)
+    }
+
+    /// Width of the integer the field is decoded into.
+    pub fn int_bytes(&self) -> usize {
+        self.bytes.next_power_of_two()
+    }
+
+    /// Rust type of that integer, e.g. `u32` for a 3-byte unsigned field.
+    pub fn int_type(&self) -> String {
+        let sign = if self.signed { 'i' } else { 'u' };
+        format!("{}{}", sign, self.int_bytes() * 8)
+    }
+}
+
+/// [`LengthField`] as used by the `read_mode/length_field` partial.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LengthFieldTemplateCtx {
+    pub bytes: usize,
+    pub signed: bool,
+    pub big_endian: bool,
+    pub int_type: String,
+    pub int_bytes: usize,
+    /// The field is narrower than `int_type` and has to be widened.
+    pub widened: bool,
+    /// Bytes added in front of a big-endian field when widening it.
+    pub padding: usize,
+    /// `be` or `le`, as in `from_be_bytes`.
+    pub endian: &'static str,
+    /// Index of the byte holding the sign bit.
+    pub sign_byte: usize,
+    /// See [`LengthField::max_value`].
+    pub max_value: u64,
+}
+
+impl From<LengthField> for LengthFieldTemplateCtx {
+    fn from(field: LengthField) -> Self {
+        LengthFieldTemplateCtx {
+            bytes: field.bytes,
+            signed: field.signed,
+            big_endian: field.big_endian,
+            int_type: field.int_type(),
+            int_bytes: field.int_bytes(),
+            widened: field.int_bytes() != field.bytes,
+            padding: field.int_bytes() - field.bytes,
+            endian: if field.big_endian { "be" } else { "le" },
+            sign_byte: if field.big_endian { 0 } else { field.bytes - 1 },
+            max_value: field.max_value(),
+        }
+    }
+}
+
+/// Header in front of every `length_prefixed` frame: `offset` bytes, then the
+/// length field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthHeader {
+    pub field: LengthField,
+    /// Bytes before the length field.
+    pub offset: usize,
+    /// Added to the length field.
+    pub adjustment: i64,
+    /// Bytes dropped from the start of every frame (defaults to the header).
+    pub num_skip: Option<usize>,
+    /// The length field counts the header too.
+    pub includes_header: bool,
+}
+
+impl LengthHeader {
+    /// Header length in bytes.
+    pub fn header_len(&self) -> usize {
+        self.offset + self.field.bytes
+    }
+
+    /// Bytes dropped from the start of every frame.
+    pub fn num_skip(&self) -> usize {
+        self.num_skip.unwrap_or(self.header_len())
+    }
+
+    /// Bytes in front of the ones the length field (plus `adjustment`)
+    /// counts: the skipped bytes, as in tokio-util, or none if the length
+    /// includes the header.
+    pub fn uncounted(&self) -> usize {
+        if self.includes_header {
+            0
+        } else {
+            self.num_skip()
+        }
+    }
+}
+
+/// Plain header: just the length field, stripped from every frame.
+impl From<LengthField> for LengthHeader {
+    fn from(field: LengthField) -> Self {
+        LengthHeader {
+            field,
+            offset: 0,
+            adjustment: 0,
+            num_skip: None,
+            includes_header: false,
+        }
+    }
+}
+
+/// [`LengthHeader`] as used by the `read_mode/length_header` partial.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LengthHeaderTemplateCtx {
+    pub field: LengthFieldTemplateCtx,
+    pub offset: usize,
+    pub header_len: usize,
+    pub num_skip: usize,
+    /// See [`LengthHeader::uncounted`].
+    pub uncounted: usize,
+    pub adjustment: i64,
+}
+
+impl From<LengthHeader> for LengthHeaderTemplateCtx {
+    fn from(header: LengthHeader) -> Self {
+        LengthHeaderTemplateCtx {
+            field: header.field.into(),
+            offset: header.offset,
+            header_len: header.header_len(),
+            num_skip: header.num_skip(),
+            uncounted: header.uncounted(),
+            adjustment: header.adjustment,
+        }
     }
 }
@@ -0,0 +1,227 @@
+// src/status.rs
+//! `netgen status`: compare a generated project with its lock and config.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Args;
+use serde::Deserialize;
+
+use crate::config::{ConfigFormat, ConfigSource};
+use crate::generator::Registry;
+use crate::lock::{checksum, read_lock, Lock};
+use crate::migrate;
+use crate::util::{resolve_out_dir, template_set};
+use crate::workspace::{
+    manifest_hash, member_source, MemberProbe, WorkspaceManifest, ROOT_TEMPLATE, WORKSPACE_KIND,
+};
+
+/// `netgen status`: is the project stale, and which files were edited by hand?
+#[derive(Args, Debug)]
+pub struct StatusCmd {
+    /// Config the project was generated from (YAML, TOML or JSON).
+    #[arg(long)]
+    pub config: String,
+
+    /// Config format (detected from the file extension by default).
+    #[arg(long, value_enum)]
+    pub format: Option<ConfigFormat>,
+
+    /// Project directory, if it was generated with `--out-dir`.
+    #[arg(long)]
+    pub out_dir: Option<String>,
+}
+
+/// State of a generated file compared with its checksum in the lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    Unchanged,
+    /// Edited by hand since it was generated.
+    Modified,
+    Missing,
+}
+
+/// Status of one generated project (or workspace root).
+#[derive(Debug, Clone)]
+pub struct ProjectStatus {
+    pub out_dir: PathBuf,
+    pub lock: Lock,
+    /// The config no longer matches the one the project was generated from.
+    pub config_changed: bool,
+    /// Templates whose source changed since generation.
+    pub changed_templates: Vec<String>,
+    /// Every file in the lock with its state on disk.
+    pub files: Vec<(String, FileState)>,
+}
+
+impl ProjectStatus {
+    /// Regenerating would produce different files.
+    pub fn is_stale(&self) -> bool {
+        self.config_changed || !self.changed_templates.is_empty()
+    }
+
+    /// Files that were edited or deleted since generation.
+    pub fn touched_files(&self) -> impl Iterator<Item = (&str, FileState)> {
+        self.files
+            .iter()
+            .filter(|(_, state)| *state != FileState::Unchanged)
+            .map(|(path, state)| (path.as_str(), *state))
+    }
+}
+
+/// Status of the project generated from `config`: one entry for a service,
+/// the root followed by every member for a workspace.
+pub fn project_status(
+    registry: &Registry,
+    config: &ConfigSource,
+    out_dir: Option<&Path>,
+) -> Result<Vec<ProjectStatus>> {
+    if config.kind()?.as_deref() == Some(WORKSPACE_KIND) {
+        return workspace_status(registry, config, out_dir);
+    }
+
+    let out_dir = match out_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => {
+            #[derive(Deserialize)]
+            struct DirProbe {
+                project_name: String,
+                out_dir: Option<String>,
+            }
+
+            let probe: DirProbe = config.parse()?;
+            PathBuf::from(resolve_out_dir(None, probe.out_dir, &probe.project_name))
+        }
+    };
+    Ok(vec![service_status(registry, config, out_dir)?])
+}
+
+fn service_status(
+    registry: &Registry,
+    config: &ConfigSource,
+    out_dir: PathBuf,
+) -> Result<ProjectStatus> {
+    let lock = read_lock(&out_dir)?;
+    let generator = match config.kind()? {
+        Some(_) => registry.for_config(config)?,
+        None => registry.require(&lock.generator)?,
+    };
+
+    let templates = template_set(lock.templates.overrides_dir.clone());
+    let rendered = generator.render_config(config, &templates)?;
+    let changed_templates = changed_templates(&lock, |name| {
+        rendered.templates.checksums.get(name).cloned()
+    });
+
+    Ok(compare(
+        out_dir,
+        lock,
+        &rendered.config_hash,
+        changed_templates,
+    ))
+}
+
+fn workspace_status(
+    registry: &Registry,
+    manifest: &ConfigSource,
+    out_dir: Option<&Path>,
+) -> Result<Vec<ProjectStatus>> {
+    let cfg: WorkspaceManifest = migrate::load(manifest)?;
+    let out_dir = match out_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::from(resolve_out_dir(None, cfg.out_dir.clone(), &cfg.name)),
+    };
+
+    let lock = read_lock(&out_dir)?;
+    let templates = template_set(lock.templates.overrides_dir.clone());
+    let root_checksum = checksum(templates.load(ROOT_TEMPLATE)?.0);
+    let changed = changed_templates(&lock, |name| {
+        (name == ROOT_TEMPLATE).then(|| root_checksum.clone())
+    });
+    let mut statuses = vec![compare(
+        out_dir.clone(),
+        lock,
+        &manifest_hash(&cfg)?,
+        changed,
+    )];
+
+    for (i, entry) in cfg.services.iter().enumerate() {
+        let source = member_source(manifest, i, entry)?;
+        let probe: MemberProbe = source.parse()?;
+        statuses.push(service_status(
+            registry,
+            &source,
+            out_dir.join(probe.project_name),
+        )?);
+    }
+
+    Ok(statuses)
+}
+
+/// Templates recorded in `lock` whose `current` checksum is different.
+fn changed_templates(lock: &Lock, current: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    lock.templates
+        .checksums
+        .iter()
+        .filter(|(name, recorded)| current(name).as_ref() != Some(*recorded))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+fn compare(
+    out_dir: PathBuf,
+    lock: Lock,
+    config_hash: &str,
+    changed_templates: Vec<String>,
+) -> ProjectStatus {
+    let files = lock
+        .files
+        .iter()
+        .map(|(path, expected)| {
+            let state = match std::fs::read(out_dir.join(path)) {
+                Err(_) => FileState::Missing,
+                Ok(contents) if checksum(&contents) == *expected => FileState::Unchanged,
+                Ok(_) => FileState::Modified,
+            };
+            (path.clone(), state)
+        })
+        .collect();
+
+    ProjectStatus {
+        config_changed: lock.config_hash != config_hash,
+        out_dir,
+        lock,
+        changed_templates,
+        files,
+    }
+}
+
+/// Print a status report.
+pub fn print_status(status: &ProjectStatus) {
+    println!(
+        "📋 {} ({}, generated by netgen {})",
+        status.out_dir.to_string_lossy(),
+        status.lock.generator,
+        status.lock.netgen_version
+    );
+
+    if status.config_changed {
+        println!("  ⚠️  stale: the config changed since generation; regenerate to apply it");
+    }
+    if !status.changed_templates.is_empty() {
+        println!(
+            "  ⚠️  stale: templates changed since generation: {}",
+            status.changed_templates.join(", ")
+        );
+    }
+    for (path, state) in status.touched_files() {
+        match state {
+            FileState::Modified => println!("  ✏️  edited by hand: {}", path),
+            FileState::Missing => println!("  ❌ missing: {}", path),
+            FileState::Unchanged => {}
+        }
+    }
+    if !status.is_stale() && status.touched_files().next().is_none() {
+        println!("  ✅ up to date with its config, no hand edits");
+    }
+}
@@ -1,13 +1,21 @@
 // src/tcp_echo.rs
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use clap::Args;
-use handlebars::Handlebars;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::read_mode::{ReadModeTemplateCtx, YamlReadMode};
-use crate::util::{read_yaml, resolve_out_dir};
+use crate::config::{ConfigFormat, ConfigSource};
+use crate::connection::{
+    default_shutdown_grace, ConfigDuration, LimitsTemplateCtx, TimeoutsTemplateCtx,
+    YamlConnectionLimits, YamlTimeouts, TCP_PARTIALS,
+};
+use crate::generator::{FileTree, GenerateArgs, Generator, OutputFile, ProjectSettings};
+use crate::post_generate::PostStep;
+use crate::read_mode::{Framing, ReadModeTemplateCtx, YamlReadMode, YamlResponse};
+use crate::templates::TemplateSet;
+use crate::validate::{check_crate_name, Issue};
 
 /// CLI command for generating TCP echo server.
 
@@ -16,6 +24,10 @@ pub struct EchoCmd {
     #[arg(long)]
     pub config: Option<String>,
 
+    /// Config format (detected from the file extension by default).
+    #[arg(long, value_enum)]
+    pub format: Option<ConfigFormat>,
+
     #[arg(short, long, default_value = "tcp-echo-server")]
     pub name: String,
 
@@ -28,17 +40,51 @@ pub struct EchoCmd {
     #[arg(long)]
     pub max_line_len: Option<usize>,
 
-    #[arg(long)]
-    pub out_dir: Option<String>,
+    /// Frame connections with a tokio-util codec instead of a read loop.
+    #[arg(long, default_value_t = false)]
+    pub codec: bool,
+
+    #[command(flatten)]
+    pub generate: GenerateArgs,
 }
 
-#[derive(Debug, Deserialize)]
+/// TCP echo server config.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct TcpEchoYamlConfig {
+    /// Crate name of the generated project.
     pub project_name: String,
+    /// Port to listen on.
     pub port: u16,
+    /// Log through `tracing` instead of stdout/stderr.
     pub tracing: bool,
+    /// How incoming bytes are split into frames.
     pub read_mode: YamlReadMode,
+    /// `loop` (default) for a hand-written read loop, `codec` for a
+    /// `tokio_util::codec` codec driven through `Framed`.
+    #[serde(default)]
+    pub framing: Framing,
+    /// How replies are framed: `raw` (default), `same` as `read_mode`, or a
+    /// read mode of their own.
+    #[serde(default)]
+    pub response: YamlResponse,
+    /// Idle, frame read and write time limits of every connection.
+    #[serde(default)]
+    pub timeouts: YamlTimeouts,
+    /// How long open connections may take to finish after SIGINT or SIGTERM
+    /// before they are closed (default `10s`).
+    #[serde(default = "default_shutdown_grace")]
+    pub shutdown_grace: ConfigDuration,
+    /// `max_connections`, `max_connections_per_ip`, `on_limit` and
+    /// `goodbye_frame`.
+    #[serde(flatten)]
+    pub limits: YamlConnectionLimits,
+    /// Output directory (defaults to `project_name`).
     pub out_dir: Option<String>,
+    /// Directory with template overrides.
+    pub templates_dir: Option<String>,
+    /// Steps run after generating: `fmt` (rustfmt) and/or `check` (`cargo check`).
+    #[serde(default)]
+    pub post_generate: Vec<PostStep>,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,91 +97,162 @@ pub struct TcpEchoTemplateCtx {
     /// is flattened to the top level for handlebars.
     #[serde(flatten)]
     pub read_mode: ReadModeTemplateCtx,
+
+    pub timeouts: TimeoutsTemplateCtx,
+    /// Rust expression of `shutdown_grace`.
+    pub shutdown_grace: String,
+    pub limits: LimitsTemplateCtx,
 }
 
 impl From<TcpEchoYamlConfig> for TcpEchoTemplateCtx {
     fn from(cfg: TcpEchoYamlConfig) -> Self {
-        let read_mode: ReadModeTemplateCtx = cfg.read_mode.into();
+        let response = cfg.response.template_ctx(&cfg.read_mode);
+        let mut read_mode: ReadModeTemplateCtx = cfg.read_mode.into();
+        read_mode.use_codec = cfg.framing == Framing::Codec;
+        read_mode.response = response;
 
         TcpEchoTemplateCtx {
             project_name: cfg.project_name,
             port: cfg.port,
             tracing_enabled: cfg.tracing,
             read_mode,
+            timeouts: cfg.timeouts.into(),
+            shutdown_grace: cfg.shutdown_grace.rust_expr(),
+            limits: cfg.limits.into(),
         }
     }
 }
 
-impl From<&EchoCmd> for TcpEchoTemplateCtx {
+impl From<&EchoCmd> for TcpEchoYamlConfig {
     fn from(cli: &EchoCmd) -> Self {
         // CLI currently only supports lines mode.
-        let read_mode = ReadModeTemplateCtx {
-            is_lines: true,
-            max_line_len: cli.max_line_len,
-            ..Default::default()
-        };
-
-        TcpEchoTemplateCtx {
+        TcpEchoYamlConfig {
             project_name: cli.name.clone(),
             port: cli.port,
-            tracing_enabled: cli.tracing,
-            read_mode,
+            tracing: cli.tracing,
+            read_mode: YamlReadMode::Lines {
+                max_line_len: cli.max_line_len,
+                validate_utf8: false,
+                on_oversize: None,
+                error_frame: None,
+            },
+            framing: if cli.codec {
+                Framing::Codec
+            } else {
+                Framing::Loop
+            },
+            response: YamlResponse::Raw,
+            timeouts: YamlTimeouts::default(),
+            shutdown_grace: default_shutdown_grace(),
+            limits: YamlConnectionLimits::default(),
+            out_dir: None,
+            templates_dir: None,
+            post_generate: Vec::new(),
         }
     }
 }
 
-/// Generate TCP echo server project from template context.
-pub fn generate_tcp_echo_project(ctx: &TcpEchoTemplateCtx, out_dir: &Path) -> Result<()> {
-    std::fs::create_dir_all(out_dir.join("src"))?;
-
-    let mut hbs = Handlebars::new();
-    // Disable HTML escaping since we're generating code, not HTML
-    hbs.register_escape_fn(handlebars::no_escape);
+impl From<&EchoCmd> for TcpEchoTemplateCtx {
+    fn from(cli: &EchoCmd) -> Self {
+        TcpEchoYamlConfig::from(cli).into()
+    }
+}
 
-    hbs.register_template_string(
-        "cargo_toml",
-        include_str!("../templates/tcp_echo/Cargo.toml.hbs"),
-    )?;
-    hbs.register_template_string("main_rs", include_str!("../templates/tcp_echo/main.rs.hbs"))?;
+/// Generator for TCP echo servers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TcpEchoGenerator;
 
-    let cargo_toml = hbs.render("cargo_toml", ctx)?;
-    std::fs::write(out_dir.join("Cargo.toml"), cargo_toml)?;
+const FILES: &[OutputFile] = &[
+    OutputFile {
+        template: "tcp_echo/Cargo.toml.hbs",
+        path: "Cargo.toml",
+    },
+    OutputFile {
+        template: "tcp_echo/main.rs.hbs",
+        path: "src/main.rs",
+    },
+];
 
-    let main_rs = hbs.render("main_rs", ctx)?;
-    std::fs::write(out_dir.join("src/main.rs"), main_rs)?;
+impl Generator for TcpEchoGenerator {
+    type Config = TcpEchoYamlConfig;
+    type Ctx = TcpEchoTemplateCtx;
 
-    Ok(())
-}
+    fn name(&self) -> &'static str {
+        "tcp-echo"
+    }
 
-/// Entry point for TCP echo subcommand.
-pub fn run_from_cli(cmd: EchoCmd) -> Result<()> {
-    if let Some(config_path) = cmd.config.as_deref() {
-        let cfg: TcpEchoYamlConfig = read_yaml(config_path)?;
-        let cfg_out_dir = cfg.out_dir.clone();
-        let ctx: TcpEchoTemplateCtx = cfg.into();
+    fn description(&self) -> &'static str {
+        "TCP echo server"
+    }
 
-        let out_dir_str = resolve_out_dir(cmd.out_dir.clone(), cfg_out_dir, &ctx.project_name);
-        let out_dir = Path::new(&out_dir_str);
+    fn files(&self) -> &'static [OutputFile] {
+        FILES
+    }
 
-        generate_tcp_echo_project(&ctx, out_dir)?;
+    fn partials(&self) -> &'static [&'static str] {
+        TCP_PARTIALS
+    }
 
-        println!(
-            "✅ Generated TCP echo project (YAML) in {}",
-            out_dir.to_string_lossy()
-        );
-        return Ok(());
+    fn settings(&self, cfg: &TcpEchoYamlConfig) -> ProjectSettings {
+        ProjectSettings {
+            project_name: cfg.project_name.clone(),
+            out_dir: cfg.out_dir.clone(),
+            templates_dir: cfg.templates_dir.clone(),
+            post_generate: cfg.post_generate.clone(),
+        }
     }
 
-    let ctx: TcpEchoTemplateCtx = (&cmd).into();
-    let out_dir_str = resolve_out_dir(cmd.out_dir.clone(), None, &ctx.project_name);
-    let out_dir = Path::new(&out_dir_str);
+    fn context(&self, cfg: TcpEchoYamlConfig) -> TcpEchoTemplateCtx {
+        cfg.into()
+    }
 
-    generate_tcp_echo_project(&ctx, out_dir)?;
+    fn validate(&self, cfg: &TcpEchoYamlConfig) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        issues.extend(check_crate_name("project_name", &cfg.project_name));
+        issues.extend(cfg.read_mode.validate("read_mode"));
+        issues.extend(cfg.response.validate("response", &cfg.read_mode));
+        issues.extend(cfg.timeouts.validate("timeouts"));
+        issues.extend(cfg.limits.validate());
+        issues
+    }
+}
 
-    println!(
-        "✅ Generated TCP echo project (CLI) in {}",
-        out_dir.to_string_lossy()
-    );
+/// Render TCP echo server project in memory, keyed by path relative to the project root.
+pub fn render_tcp_echo_project(ctx: &TcpEchoTemplateCtx) -> Result<FileTree> {
+    Ok(TcpEchoGenerator
+        .render(ctx, &TemplateSet::embedded())?
+        .files)
+}
 
+/// Generate TCP echo server project from template context.
+pub fn generate_tcp_echo_project(ctx: &TcpEchoTemplateCtx, out_dir: &Path) -> Result<()> {
+    generate_tcp_echo_project_with_templates(ctx, out_dir, &TemplateSet::embedded())?;
     Ok(())
 }
+
+/// Same as [`generate_tcp_echo_project`], but loads templates from `templates`.
+/// Returns the template overrides that were used.
+pub fn generate_tcp_echo_project_with_templates(
+    ctx: &TcpEchoTemplateCtx,
+    out_dir: &Path,
+    templates: &TemplateSet,
+) -> Result<Vec<PathBuf>> {
+    TcpEchoGenerator.generate(ctx, out_dir, templates)
+}
+
+impl EchoCmd {
+    /// Config for this command: the config file if `--config` is given,
+    /// otherwise a config built from the CLI flags.
+    pub fn config_source(&self) -> Result<ConfigSource> {
+        if let Some(config_path) = self.config.as_deref() {
+            return ConfigSource::read(config_path, self.format);
+        }
+
+        let cfg = TcpEchoYamlConfig::from(self);
+        Ok(ConfigSource::new(
+            "command line",
+            serde_yaml::to_string(&cfg)?,
+            ConfigFormat::Yaml,
+        ))
+    }
+}
@@ -1,34 +1,79 @@
 // src/tcp_worker.rs
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use clap::Args;
-use handlebars::Handlebars;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::read_mode::{ReadModeTemplateCtx, YamlReadMode};
-use crate::util::{read_yaml, resolve_out_dir};
+use crate::config::ConfigFormat;
+use crate::connection::{
+    default_shutdown_grace, ConfigDuration, LimitsTemplateCtx, TimeoutsTemplateCtx,
+    YamlConnectionLimits, YamlTimeouts, TCP_PARTIALS,
+};
+use crate::generator::{FileTree, GenerateArgs, Generator, OutputFile, ProjectSettings};
+use crate::post_generate::PostStep;
+use crate::read_mode::{Framing, ReadModeTemplateCtx, YamlReadMode, YamlResponse};
+use crate::templates::TemplateSet;
+use crate::validate::{check_crate_name, check_positive, Issue};
 
 /// CLI command for generating TCP worker-pool server.
 
 #[derive(Debug, Args)]
 pub struct WorkerCmd {
+    /// Path to config file (YAML, TOML or JSON).
     #[arg(long)]
     pub config: String,
 
-    #[arg(long)]
-    pub out_dir: Option<String>,
+    /// Config format (detected from the file extension by default).
+    #[arg(long, value_enum)]
+    pub format: Option<ConfigFormat>,
+
+    #[command(flatten)]
+    pub generate: GenerateArgs,
 }
 
-#[derive(Debug, Deserialize)]
+/// TCP worker-pool server config.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct TcpWorkerYamlConfig {
+    /// Crate name of the generated project.
     pub project_name: String,
+    /// Port to listen on.
     pub port: u16,
+    /// Log through `tracing` instead of stdout/stderr.
     pub tracing: bool,
+    /// Number of workers in the pool.
     pub workers: usize,
+    /// Capacity of the MPSC channel between connections and workers.
     pub event_buffer: usize,
+    /// How incoming bytes are split into frames.
     pub read_mode: YamlReadMode,
+    /// `loop` (default) for a hand-written read loop, `codec` for a
+    /// `tokio_util::codec` codec driven through `Framed`.
+    #[serde(default)]
+    pub framing: Framing,
+    /// How replies are framed: `raw` (default), `same` as `read_mode`, or a
+    /// read mode of their own.
+    #[serde(default)]
+    pub response: YamlResponse,
+    /// Idle, frame read and write time limits of every connection.
+    #[serde(default)]
+    pub timeouts: YamlTimeouts,
+    /// How long open connections may take to finish after SIGINT or SIGTERM
+    /// before they are closed (default `10s`).
+    #[serde(default = "default_shutdown_grace")]
+    pub shutdown_grace: ConfigDuration,
+    /// `max_connections`, `max_connections_per_ip`, `on_limit` and
+    /// `goodbye_frame`.
+    #[serde(flatten)]
+    pub limits: YamlConnectionLimits,
+    /// Output directory (defaults to `project_name`).
     pub out_dir: Option<String>,
+    /// Directory with template overrides.
+    pub templates_dir: Option<String>,
+    /// Steps run after generating: `fmt` (rustfmt) and/or `check` (`cargo check`).
+    #[serde(default)]
+    pub post_generate: Vec<PostStep>,
 }
 
 #[derive(Debug, Serialize)]
@@ -42,11 +87,19 @@ pub struct TcpWorkerTemplateCtx {
     /// Everything related to read_mode is flattened to the top level.
     #[serde(flatten)]
     pub read_mode: ReadModeTemplateCtx,
+
+    pub timeouts: TimeoutsTemplateCtx,
+    /// Rust expression of `shutdown_grace`.
+    pub shutdown_grace: String,
+    pub limits: LimitsTemplateCtx,
 }
 
 impl From<TcpWorkerYamlConfig> for TcpWorkerTemplateCtx {
     fn from(cfg: TcpWorkerYamlConfig) -> Self {
-        let read_mode: ReadModeTemplateCtx = cfg.read_mode.into();
+        let response = cfg.response.template_ctx(&cfg.read_mode);
+        let mut read_mode: ReadModeTemplateCtx = cfg.read_mode.into();
+        read_mode.use_codec = cfg.framing == Framing::Codec;
+        read_mode.response = response;
 
         TcpWorkerTemplateCtx {
             project_name: cfg.project_name,
@@ -55,51 +108,93 @@ impl From<TcpWorkerYamlConfig> for TcpWorkerTemplateCtx {
             workers: cfg.workers,
             event_buffer: cfg.event_buffer,
             read_mode,
+            timeouts: cfg.timeouts.into(),
+            shutdown_grace: cfg.shutdown_grace.rust_expr(),
+            limits: cfg.limits.into(),
         }
     }
 }
 
-/// Generate TCP worker-pool server project from template context.
-pub fn generate_tcp_worker_project(ctx: &TcpWorkerTemplateCtx, out_dir: &Path) -> Result<()> {
-    std::fs::create_dir_all(out_dir.join("src"))?;
-
-    let mut hbs = Handlebars::new();
-    // Disable HTML escaping since we're generating code, not HTML
-    hbs.register_escape_fn(handlebars::no_escape);
-
-    hbs.register_template_string(
-        "cargo_toml",
-        include_str!("../templates/tcp_worker/Cargo.toml.hbs"),
-    )?;
-    hbs.register_template_string(
-        "main_rs",
-        include_str!("../templates/tcp_worker/main.rs.hbs"),
-    )?;
+/// Generator for TCP worker-pool servers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TcpWorkerGenerator;
+
+const FILES: &[OutputFile] = &[
+    OutputFile {
+        template: "tcp_worker/Cargo.toml.hbs",
+        path: "Cargo.toml",
+    },
+    OutputFile {
+        template: "tcp_worker/main.rs.hbs",
+        path: "src/main.rs",
+    },
+];
+
+impl Generator for TcpWorkerGenerator {
+    type Config = TcpWorkerYamlConfig;
+    type Ctx = TcpWorkerTemplateCtx;
+
+    fn name(&self) -> &'static str {
+        "tcp-worker"
+    }
 
-    let cargo_toml = hbs.render("cargo_toml", ctx)?;
-    std::fs::write(out_dir.join("Cargo.toml"), cargo_toml)?;
+    fn description(&self) -> &'static str {
+        "TCP worker-pool server"
+    }
 
-    let main_rs = hbs.render("main_rs", ctx)?;
-    std::fs::write(out_dir.join("src/main.rs"), main_rs)?;
+    fn files(&self) -> &'static [OutputFile] {
+        FILES
+    }
 
-    Ok(())
-}
+    fn partials(&self) -> &'static [&'static str] {
+        TCP_PARTIALS
+    }
 
-/// Entry point for TCP worker subcommand.
-pub fn run_from_cli(cmd: WorkerCmd) -> Result<()> {
-    let cfg: TcpWorkerYamlConfig = read_yaml(&cmd.config)?;
-    let cfg_out_dir = cfg.out_dir.clone();
-    let ctx: TcpWorkerTemplateCtx = cfg.into();
+    fn settings(&self, cfg: &TcpWorkerYamlConfig) -> ProjectSettings {
+        ProjectSettings {
+            project_name: cfg.project_name.clone(),
+            out_dir: cfg.out_dir.clone(),
+            templates_dir: cfg.templates_dir.clone(),
+            post_generate: cfg.post_generate.clone(),
+        }
+    }
 
-    let out_dir_str = resolve_out_dir(cmd.out_dir.clone(), cfg_out_dir, &ctx.project_name);
-    let out_dir = Path::new(&out_dir_str);
+    fn context(&self, cfg: TcpWorkerYamlConfig) -> TcpWorkerTemplateCtx {
+        cfg.into()
+    }
 
-    generate_tcp_worker_project(&ctx, out_dir)?;
+    fn validate(&self, cfg: &TcpWorkerYamlConfig) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        issues.extend(check_crate_name("project_name", &cfg.project_name));
+        issues.extend(check_positive("workers", cfg.workers, 4));
+        issues.extend(check_positive("event_buffer", cfg.event_buffer, 1024));
+        issues.extend(cfg.read_mode.validate("read_mode"));
+        issues.extend(cfg.response.validate("response", &cfg.read_mode));
+        issues.extend(cfg.timeouts.validate("timeouts"));
+        issues.extend(cfg.limits.validate());
+        issues
+    }
+}
 
-    println!(
-        "✅ Generated TCP worker server project in {}",
-        out_dir.to_string_lossy()
-    );
+/// Render TCP worker-pool server project in memory, keyed by path relative to the project root.
+pub fn render_tcp_worker_project(ctx: &TcpWorkerTemplateCtx) -> Result<FileTree> {
+    Ok(TcpWorkerGenerator
+        .render(ctx, &TemplateSet::embedded())?
+        .files)
+}
 
+/// Generate TCP worker-pool server project from template context.
+pub fn generate_tcp_worker_project(ctx: &TcpWorkerTemplateCtx, out_dir: &Path) -> Result<()> {
+    generate_tcp_worker_project_with_templates(ctx, out_dir, &TemplateSet::embedded())?;
     Ok(())
 }
+
+/// Same as [`generate_tcp_worker_project`], but loads templates from `templates`.
+/// Returns the template overrides that were used.
+pub fn generate_tcp_worker_project_with_templates(
+    ctx: &TcpWorkerTemplateCtx,
+    out_dir: &Path,
+    templates: &TemplateSet,
+) -> Result<Vec<PathBuf>> {
+    TcpWorkerGenerator.generate(ctx, out_dir, templates)
+}
@@ -0,0 +1,205 @@
+// src/templates.rs
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use handlebars::Handlebars;
+
+use crate::lock::checksum;
+
+/// Templates compiled into the binary, keyed by their path relative to `templates/`.
+const EMBEDDED: &[(&str, &str)] = &[
+    (
+        "tcp_echo/Cargo.toml.hbs",
+        include_str!("../templates/tcp_echo/Cargo.toml.hbs"),
+    ),
+    (
+        "tcp_echo/main.rs.hbs",
+        include_str!("../templates/tcp_echo/main.rs.hbs"),
+    ),
+    (
+        "tcp_worker/Cargo.toml.hbs",
+        include_str!("../templates/tcp_worker/Cargo.toml.hbs"),
+    ),
+    (
+        "tcp_worker/main.rs.hbs",
+        include_str!("../templates/tcp_worker/main.rs.hbs"),
+    ),
+    (
+        "http_axum/Cargo.toml.hbs",
+        include_str!("../templates/http_axum/Cargo.toml.hbs"),
+    ),
+    (
+        "http_axum/main.rs.hbs",
+        include_str!("../templates/http_axum/main.rs.hbs"),
+    ),
+    (
+        "http_axum/handlers.rs.hbs",
+        include_str!("../templates/http_axum/handlers.rs.hbs"),
+    ),
+    (
+        "workspace/Cargo.toml.hbs",
+        include_str!("../templates/workspace/Cargo.toml.hbs"),
+    ),
+    (
+        "read_mode/codec.rs.hbs",
+        include_str!("../templates/read_mode/codec.rs.hbs"),
+    ),
+    (
+        "read_mode/delimiter.rs.hbs",
+        include_str!("../templates/read_mode/delimiter.rs.hbs"),
+    ),
+    (
+        "read_mode/length_field.rs.hbs",
+        include_str!("../templates/read_mode/length_field.rs.hbs"),
+    ),
+    (
+        "read_mode/length_header.rs.hbs",
+        include_str!("../templates/read_mode/length_header.rs.hbs"),
+    ),
+    (
+        "read_mode/line.rs.hbs",
+        include_str!("../templates/read_mode/line.rs.hbs"),
+    ),
+    (
+        "read_mode/oversize.rs.hbs",
+        include_str!("../templates/read_mode/oversize.rs.hbs"),
+    ),
+    (
+        "read_mode/response.rs.hbs",
+        include_str!("../templates/read_mode/response.rs.hbs"),
+    ),
+    (
+        "read_mode/varint.rs.hbs",
+        include_str!("../templates/read_mode/varint.rs.hbs"),
+    ),
+    (
+        "shutdown/signal.rs.hbs",
+        include_str!("../templates/shutdown/signal.rs.hbs"),
+    ),
+    (
+        "tcp/accept.rs.hbs",
+        include_str!("../templates/tcp/accept.rs.hbs"),
+    ),
+    (
+        "tcp/limits.rs.hbs",
+        include_str!("../templates/tcp/limits.rs.hbs"),
+    ),
+    (
+        "tcp/reply.rs.hbs",
+        include_str!("../templates/tcp/reply.rs.hbs"),
+    ),
+    (
+        "tcp/stopping.rs.hbs",
+        include_str!("../templates/tcp/stopping.rs.hbs"),
+    ),
+    (
+        "tcp/timeouts.rs.hbs",
+        include_str!("../templates/tcp/timeouts.rs.hbs"),
+    ),
+];
+
+/// Embedded template by its relative path (e.g. `tcp_echo/main.rs.hbs`).
+pub fn embedded(rel_path: &str) -> Option<&'static str> {
+    EMBEDDED
+        .iter()
+        .find(|(path, _)| *path == rel_path)
+        .map(|(_, src)| *src)
+}
+
+/// Where templates are loaded from.
+///
+/// Templates are looked up in the override directory first (using the same
+/// layout as `templates/` in this repository) and fall back to the copies
+/// embedded into the binary.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateSet {
+    overrides_dir: Option<PathBuf>,
+}
+
+impl TemplateSet {
+    /// Only the embedded templates.
+    pub fn embedded() -> Self {
+        Self::default()
+    }
+
+    /// Embedded templates, overridden by files found in `dir`.
+    pub fn with_overrides(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            overrides_dir: Some(dir.into()),
+        }
+    }
+
+    pub fn overrides_dir(&self) -> Option<&Path> {
+        self.overrides_dir.as_deref()
+    }
+
+    /// Load the template source for `rel_path`.
+    /// Returns the source and, if an override was used, the path it came from.
+    pub fn load(&self, rel_path: &str) -> Result<(String, Option<PathBuf>)> {
+        if let Some(dir) = &self.overrides_dir {
+            let candidate = dir.join(rel_path);
+            if candidate.is_file() {
+                let src = std::fs::read_to_string(&candidate).with_context(|| {
+                    format!("failed to read template override {}", candidate.display())
+                })?;
+                return Ok((src, Some(candidate)));
+            }
+        }
+
+        let src = embedded(rel_path).with_context(|| format!("unknown template {}", rel_path))?;
+        Ok((src.to_string(), None))
+    }
+
+    /// Where `rel_path` would be loaded from: the override file, or the embedded template.
+    pub fn source_of(&self, rel_path: &str) -> String {
+        match &self.overrides_dir {
+            Some(dir) if dir.join(rel_path).is_file() => {
+                dir.join(rel_path).to_string_lossy().into_owned()
+            }
+            _ => format!("{} (embedded)", rel_path),
+        }
+    }
+
+    /// Register template `rel_path` under `name`.
+    pub fn register(
+        &self,
+        hbs: &mut Handlebars<'_>,
+        name: &str,
+        rel_path: &str,
+    ) -> Result<Registered> {
+        let (src, override_path) = self.load(rel_path)?;
+        let checksum = checksum(&src);
+        hbs.register_template_string(name, src)
+            .with_context(|| format!("failed to parse template {}", rel_path))?;
+        Ok(Registered {
+            override_path,
+            checksum,
+        })
+    }
+}
+
+/// Name a partial is included under: its relative path without extensions
+/// (`read_mode/length_field.rs.hbs` is `{{> read_mode/length_field}}`).
+pub fn partial_name(rel_path: &str) -> &str {
+    let file_start = rel_path.rfind('/').map_or(0, |i| i + 1);
+    match rel_path[file_start..].find('.') {
+        Some(i) => &rel_path[..file_start + i],
+        None => rel_path,
+    }
+}
+
+/// Template registered by [`TemplateSet::register`].
+#[derive(Debug, Clone)]
+pub struct Registered {
+    /// Path of the override, if the template came from the override directory.
+    pub override_path: Option<PathBuf>,
+    /// Checksum of the template source.
+    pub checksum: String,
+}
+
+/// Print which template overrides were used during generation.
+pub fn report_overrides(overrides: &[PathBuf]) {
+    for path in overrides {
+        println!("🔧 Using template override {}", path.to_string_lossy());
+    }
+}
@@ -1,14 +1,4 @@
-use std::{fs, path::Path};
-
-use anyhow::Result;
-use serde::de::DeserializeOwned;
-
-/// Read YAML file of arbitrary type.
-pub fn read_yaml<T: DeserializeOwned>(path: &str) -> Result<T> {
-    let s = fs::read_to_string(path)?;
-    let value = serde_yaml::from_str(&s)?;
-    Ok(value)
-}
+use crate::templates::TemplateSet;
 
 /// Output directory selection logic:
 /// 1) cli_out_dir (if provided),
@@ -24,10 +14,11 @@ pub fn resolve_out_dir(
         .unwrap_or_else(|| default_name.to_string())
 }
 
-/// Create directory and return Path.
-pub fn ensure_out_dir(path_str: &str) -> Result<&Path> {
-    use std::path::Path;
-    let path = Path::new(path_str);
-    std::fs::create_dir_all(path)?;
-    Ok(path)
+/// Template set for an optional override directory
+/// (CLI `--templates` takes precedence over YAML `templates_dir`).
+pub fn template_set(templates_dir: Option<String>) -> TemplateSet {
+    match templates_dir {
+        Some(dir) => TemplateSet::with_overrides(dir),
+        None => TemplateSet::embedded(),
+    }
 }
@@ -0,0 +1,403 @@
+// src/validate.rs
+use std::collections::HashMap;
+use std::fmt;
+
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+use crate::config::{line_column, ConfigSource};
+
+/// A semantic problem in a config, addressed by its key path
+/// (e.g. `routes[1].handler` or `read_mode.len_bytes`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub path: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl Issue {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+
+    pub fn suggest(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+/// An [`Issue`] with its position in the config file (1-based line and column).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub issue: Issue,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+/// All problems found in one config.
+#[derive(Debug, Clone)]
+pub struct ValidationError {
+    pub origin: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationError {
+    /// Error for issues in `source`, located by their key paths.
+    pub fn located(source: &ConfigSource, issues: Vec<Issue>) -> Self {
+        let positions = source.positions();
+        let diagnostics = issues
+            .into_iter()
+            .map(|issue| {
+                let pos = positions.get(&issue.path).copied();
+                Diagnostic {
+                    issue,
+                    line: pos.map(|(line, _)| line),
+                    column: pos.map(|(_, column)| column),
+                }
+            })
+            .collect();
+
+        Self {
+            origin: source.origin.clone(),
+            diagnostics,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} has {} problem(s):",
+            self.origin,
+            self.diagnostics.len()
+        )?;
+        for d in &self.diagnostics {
+            match (d.line, d.column) {
+                (Some(line), Some(column)) => write!(f, "  {}:{}:{}: ", self.origin, line, column)?,
+                _ => write!(f, "  {}: ", self.origin)?,
+            }
+            writeln!(f, "{}: {}", d.issue.path, d.issue.message)?;
+            if let Some(suggestion) = &d.issue.suggestion {
+                writeln!(f, "      help: {}", suggestion)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Attach source positions to `issues`. Returns an error if there are any.
+pub fn check(source: &ConfigSource, issues: Vec<Issue>) -> Result<(), ValidationError> {
+    if issues.is_empty() {
+        return Ok(());
+    }
+
+    Err(ValidationError::located(source, issues))
+}
+
+/// Map every key path in a YAML document to its 1-based `(line, column)`.
+/// Mapping entries point at the key, sequence items at the item.
+pub fn yaml_positions(text: &str) -> HashMap<String, (usize, usize)> {
+    let mut locator = Locator::default();
+    let mut parser = Parser::new_from_str(text);
+    // Syntax errors are reported by serde when the config is parsed.
+    let _ = parser.load(&mut locator, false);
+    locator.positions
+}
+
+/// Same as [`yaml_positions`], for TOML documents.
+pub fn toml_positions(text: &str) -> HashMap<String, (usize, usize)> {
+    let mut positions = HashMap::new();
+    // Syntax errors are reported by serde when the config is parsed.
+    if let Ok(doc) = toml_edit::Document::parse(text) {
+        toml_walk_table(doc.as_table(), "", text, &mut positions);
+    }
+    positions
+}
+
+fn toml_record(
+    positions: &mut HashMap<String, (usize, usize)>,
+    path: String,
+    span: Option<std::ops::Range<usize>>,
+    text: &str,
+) {
+    if let Some(span) = span {
+        positions
+            .entry(path)
+            .or_insert_with(|| line_column(text, span.start));
+    }
+}
+
+fn toml_walk_table(
+    table: &toml_edit::Table,
+    path: &str,
+    text: &str,
+    positions: &mut HashMap<String, (usize, usize)>,
+) {
+    for (name, _) in table.iter() {
+        let Some((key, item)) = table.get_key_value(name) else {
+            continue;
+        };
+        let child = join(path, name);
+        toml_record(positions, child.clone(), key.span(), text);
+        toml_walk_item(item, &child, text, positions);
+    }
+}
+
+fn toml_walk_item(
+    item: &toml_edit::Item,
+    path: &str,
+    text: &str,
+    positions: &mut HashMap<String, (usize, usize)>,
+) {
+    match item {
+        toml_edit::Item::Table(table) => toml_walk_table(table, path, text, positions),
+        toml_edit::Item::ArrayOfTables(tables) => {
+            for (i, table) in tables.iter().enumerate() {
+                let child = format!("{}[{}]", path, i);
+                toml_record(positions, child.clone(), table.span(), text);
+                toml_walk_table(table, &child, text, positions);
+            }
+        }
+        toml_edit::Item::Value(value) => toml_walk_value(value, path, text, positions),
+        toml_edit::Item::None => {}
+    }
+}
+
+fn toml_walk_value(
+    value: &toml_edit::Value,
+    path: &str,
+    text: &str,
+    positions: &mut HashMap<String, (usize, usize)>,
+) {
+    match value {
+        toml_edit::Value::InlineTable(table) => {
+            for (name, _) in table.iter() {
+                let Some((key, item)) = table.get_key_value(name) else {
+                    continue;
+                };
+                let child = join(path, name);
+                toml_record(positions, child.clone(), key.span(), text);
+                toml_walk_item(item, &child, text, positions);
+            }
+        }
+        toml_edit::Value::Array(array) => {
+            for (i, item) in array.iter().enumerate() {
+                let child = format!("{}[{}]", path, i);
+                toml_record(positions, child.clone(), item.span(), text);
+                toml_walk_value(item, &child, text, positions);
+            }
+        }
+        _ => {}
+    }
+}
+
+enum Frame {
+    Map { path: String, key: Option<String> },
+    Seq { path: String, index: usize },
+}
+
+#[derive(Default)]
+struct Locator {
+    stack: Vec<Frame>,
+    positions: HashMap<String, (usize, usize)>,
+}
+
+impl Locator {
+    fn record(&mut self, path: &str, mark: Marker) {
+        self.positions
+            .entry(path.to_string())
+            .or_insert((mark.line(), mark.col() + 1));
+    }
+
+    /// Path of a node that starts at `mark` as a value (not a key).
+    fn value_path(&mut self, mark: Marker) -> String {
+        let path = match self.stack.last_mut() {
+            None => String::new(),
+            Some(Frame::Map { path, key }) => join(path, key.take().as_deref().unwrap_or("?")),
+            Some(Frame::Seq { path, index }) => {
+                let item = format!("{}[{}]", path, index);
+                *index += 1;
+                item
+            }
+        };
+        self.record(&path, mark);
+        path
+    }
+}
+
+impl MarkedEventReceiver for Locator {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Map {
+                    path,
+                    key: key @ None,
+                }) = self.stack.last_mut()
+                {
+                    let key_path = join(path, &value);
+                    *key = Some(value);
+                    self.record(&key_path, mark);
+                } else {
+                    self.value_path(mark);
+                }
+            }
+            Event::Alias(_) => {
+                self.value_path(mark);
+            }
+            Event::MappingStart(..) => {
+                let path = self.value_path(mark);
+                self.stack.push(Frame::Map { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.value_path(mark);
+                self.stack.push(Frame::Seq { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Check that `name` is a valid Cargo package name.
+pub fn check_crate_name(path: &str, name: &str) -> Option<Issue> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    let starts_ok = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+
+    if valid_chars && starts_ok && !RUST_KEYWORDS.contains(&name) {
+        return None;
+    }
+
+    let message = if name.is_empty() {
+        "crate name must not be empty".to_string()
+    } else if RUST_KEYWORDS.contains(&name) {
+        format!(
+            "`{}` is a Rust keyword and cannot be used as a crate name",
+            name
+        )
+    } else {
+        format!(
+            "`{}` is not a valid crate name (use ASCII letters, digits, `-` and `_`, starting with a letter or `_`)",
+            name
+        )
+    };
+
+    Some(Issue::new(path, message).suggest(format!("{}: {}", leaf(path), suggest_crate_name(name))))
+}
+
+fn suggest_crate_name(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        return format!("{}-service", name);
+    }
+
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let out = out.trim_start_matches(|c: char| !c.is_ascii_alphabetic());
+    let out = out.trim_end_matches('-');
+
+    if out.is_empty() {
+        "my-service".to_string()
+    } else {
+        out.to_string()
+    }
+}
+
+/// Check that `name` is a valid Rust identifier.
+pub fn check_identifier(path: &str, name: &str) -> Option<Issue> {
+    let valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let starts_ok = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+
+    if valid_chars && starts_ok && name != "_" && !RUST_KEYWORDS.contains(&name) {
+        return None;
+    }
+
+    let message = if RUST_KEYWORDS.contains(&name) {
+        format!(
+            "`{}` is a Rust keyword and cannot be used as a function name",
+            name
+        )
+    } else {
+        format!("`{}` is not a valid Rust identifier", name)
+    };
+
+    Some(Issue::new(path, message).suggest(format!("{}: {}", leaf(path), suggest_identifier(name))))
+}
+
+/// Snake-case identifier derived from `name`.
+pub fn suggest_identifier(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        return format!("{}_handler", name);
+    }
+
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    let out = out.trim_end_matches('_');
+
+    match out.chars().next() {
+        None => "handler".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("handler_{}", out),
+        Some(_) => out.to_string(),
+    }
+}
+
+/// Check that a numeric setting is at least 1.
+pub fn check_positive(path: &str, value: usize, example: usize) -> Option<Issue> {
+    if value > 0 {
+        return None;
+    }
+
+    Some(Issue::new(path, "must be at least 1").suggest(format!("{}: {}", leaf(path), example)))
+}
+
+/// Last segment of a key path (`routes[1].handler` -> `handler`).
+fn leaf(path: &str) -> &str {
+    path.rsplit('.').next().unwrap_or(path)
+}
@@ -0,0 +1,433 @@
+// src/workspace.rs
+//! Multi-service manifests: several service configs generated into one Cargo workspace.
+//!
+//! Every member is produced by its regular generator. Its `Cargo.toml` is then
+//! rewritten to inherit dependencies from the workspace, and the versions are
+//! collected into `[workspace.dependencies]` of the root `Cargo.toml`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use handlebars::Handlebars;
+use schemars::{schema_for, JsonSchema, Schema};
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, InlineTable, Item, Value};
+
+use crate::config::{ConfigFormat, ConfigSource};
+use crate::generator::{
+    with_kind_and_version, write_tree, FileTree, GenerateArgs, Registry, Rendered,
+};
+use crate::lock::{checksum, write_lock, Lock, TemplatesLock};
+use crate::migrate;
+use crate::out_dir::{foreign_entry, prepare_out_dir, OverwritePolicy};
+use crate::post_generate::{check_project, file_origins, with_config, PostStep, PostSteps};
+use crate::preview::{plan_changes, print_diff, print_dry_run};
+use crate::util::{resolve_out_dir, template_set};
+use crate::validate::{check, Issue, ValidationError};
+
+/// `kind` of a workspace manifest.
+pub const WORKSPACE_KIND: &str = "workspace";
+
+/// Template of the workspace root `Cargo.toml`.
+pub(crate) const ROOT_TEMPLATE: &str = "workspace/Cargo.toml.hbs";
+
+/// Workspace manifest.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct WorkspaceManifest {
+    /// Name of the workspace, also the default output directory.
+    pub name: String,
+    /// Output directory (defaults to `name`).
+    pub out_dir: Option<String>,
+    /// Member services. Each one is generated into `<out_dir>/<project_name>`.
+    pub services: Vec<ServiceEntry>,
+    /// Steps run after generating. `fmt` applies to every member, `check`
+    /// checks the whole workspace.
+    #[serde(default)]
+    pub post_generate: Vec<PostStep>,
+}
+
+/// One member of a workspace.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ServiceEntry {
+    /// Config file, relative to the manifest.
+    File {
+        /// Path of the config, relative to the manifest.
+        path: String,
+        /// Config format (detected from the file extension by default).
+        format: Option<ConfigFormat>,
+    },
+    /// Config written inline. Like any config used by `netgen generate`,
+    /// it needs a `kind` key.
+    Inline(serde_json::Value),
+}
+
+/// JSON Schema of a workspace manifest, including the `kind` and `version` keys.
+pub fn workspace_schema() -> Schema {
+    with_kind_and_version(schema_for!(WorkspaceManifest), WORKSPACE_KIND)
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceTemplateCtx {
+    pub members: Vec<String>,
+    pub dependencies: Vec<DependencyTemplate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyTemplate {
+    pub name: String,
+    /// TOML value, e.g. `"1"` or `{ version = "1", default-features = false }`.
+    pub spec: String,
+}
+
+/// Generate the workspace described by `manifest`. Returns the workspace directory.
+pub fn generate_workspace(
+    registry: &Registry,
+    manifest: &ConfigSource,
+    args: &GenerateArgs,
+) -> Result<PathBuf> {
+    let cfg: WorkspaceManifest = migrate::load(manifest)?;
+    if cfg.services.is_empty() {
+        check(
+            manifest,
+            vec![Issue::new("services", "at least one service is required")],
+        )?;
+    }
+
+    let out_dir = PathBuf::from(resolve_out_dir(
+        args.out_dir.clone(),
+        cfg.out_dir.clone(),
+        &cfg.name,
+    ));
+    let steps = PostSteps::new(args, &cfg.post_generate);
+    let preview = args.preview();
+
+    // Render and validate every member before anything is written, so a
+    // broken member leaves the workspace untouched.
+    let mut members: Vec<String> = Vec::new();
+    let mut sources = Vec::new();
+    let mut cargo_tomls = Vec::new();
+    let mut run_check = steps.check;
+    for (i, entry) in cfg.services.iter().enumerate() {
+        let source = member_source(manifest, i, entry)?;
+        let MemberProbe {
+            project_name: member,
+            templates_dir,
+            post_generate,
+        } = source.parse()?;
+        run_check |= post_generate.contains(&PostStep::Check);
+        if members.contains(&member) {
+            check(
+                manifest,
+                vec![Issue::new(
+                    format!("services[{}]", i),
+                    format!("another service is already called `{}`", member),
+                )
+                .suggest("give each service its own project_name")],
+            )?;
+        }
+
+        let result = registry.for_config(&source).and_then(|generator| {
+            let templates = template_set(args.templates.clone().or(templates_dir.clone()));
+            generator.render_config(&source, &templates)
+        });
+        let rendered = match result {
+            Ok(rendered) => rendered,
+            Err(err) => {
+                return Err(match entry {
+                    ServiceEntry::Inline(_) => relocate(manifest, i, err),
+                    ServiceEntry::File { .. } => err,
+                })
+            }
+        };
+        if let Some(cargo_toml) = rendered.files.get("Cargo.toml") {
+            cargo_tomls.push((member.clone(), cargo_toml.clone()));
+        }
+        if !preview && args.overwrite_policy() == OverwritePolicy::Refuse {
+            prepare_out_dir(&out_dir.join(&member), OverwritePolicy::Refuse)?;
+        }
+        members.push(member);
+        sources.push((source, templates_dir));
+    }
+    let (dependencies, notes) = shared_dependencies(&cargo_tomls)?;
+
+    if !preview {
+        if let Some(backup) = prepare_out_dir(&out_dir, args.overwrite_policy())? {
+            println!(
+                "📦 Moved existing {} to {}",
+                out_dir.to_string_lossy(),
+                backup.to_string_lossy()
+            );
+        }
+    } else if let Some(entry) = foreign_entry(&out_dir)? {
+        println!(
+            "⚠️  {} contains files not generated by netgen (e.g. {}); \
+             generating would need --force or --backup",
+            out_dir.to_string_lossy(),
+            entry.to_string_lossy()
+        );
+    }
+
+    let mut origins = Vec::new();
+    for (member, (source, templates_dir)) in members.iter().zip(sources) {
+        // Members are checked together once the workspace root exists.
+        let member_args = GenerateArgs {
+            out_dir: Some(out_dir.join(member).to_string_lossy().into_owned()),
+            fmt: steps.fmt,
+            check: false,
+            ..args.clone()
+        };
+        let mut edit = |rendered: &mut Rendered| {
+            if let Some(contents) = rendered.files.get_mut("Cargo.toml") {
+                *contents = inherit_dependencies(member, contents, &dependencies)?;
+            }
+            Ok(())
+        };
+
+        let generator = registry.for_config(&source)?;
+        let templates = template_set(args.templates.clone().or(templates_dir));
+        origins.extend(file_origins(
+            generator.files(),
+            &templates,
+            &format!("{}/", member),
+        ));
+        generator.run_with(&source, &member_args, &mut edit)?;
+    }
+
+    let ctx = WorkspaceTemplateCtx {
+        members,
+        dependencies: dependencies
+            .into_iter()
+            .map(|(name, spec)| DependencyTemplate { name, spec })
+            .collect(),
+    };
+
+    let templates = template_set(args.templates.clone());
+    let mut hbs = Handlebars::new();
+    hbs.register_escape_fn(handlebars::no_escape);
+    let registered = templates.register(&mut hbs, "Cargo.toml", ROOT_TEMPLATE)?;
+    let cargo_toml = hbs.render("Cargo.toml", &ctx)?;
+    let files = FileTree::from([("Cargo.toml".to_string(), cargo_toml)]);
+
+    if let Some(path) = registered.override_path {
+        println!("🔧 Using template override {}", path.to_string_lossy());
+    }
+    for note in &notes {
+        println!("ℹ️  {}", note);
+    }
+
+    if preview {
+        let changes = plan_changes(&out_dir, &files)?;
+        if args.diff {
+            print_diff(&changes);
+        }
+        if args.dry_run {
+            print_dry_run(&out_dir, &changes);
+        }
+        return Ok(out_dir);
+    }
+
+    write_tree(&out_dir, &files)?;
+    let lock = Lock::new(
+        WORKSPACE_KIND,
+        manifest_hash(&cfg)?,
+        TemplatesLock {
+            overrides_dir: args.templates.clone(),
+            checksums: BTreeMap::from([(ROOT_TEMPLATE.to_string(), registered.checksum)]),
+        },
+        &files,
+    );
+    write_lock(&out_dir, &lock)?;
+
+    println!(
+        "✅ Generated workspace with {} services in {}",
+        ctx.members.len(),
+        out_dir.to_string_lossy()
+    );
+
+    if run_check {
+        check_project(&out_dir, &origins).map_err(|err| with_config(err, &manifest.origin))?;
+    }
+
+    Ok(out_dir)
+}
+
+/// Hash of the resolved manifest, recorded in the workspace lock.
+pub fn manifest_hash(cfg: &WorkspaceManifest) -> Result<String> {
+    Ok(checksum(serde_json::to_string(cfg)?))
+}
+
+/// Config of service `index`, read from its file or taken from the manifest.
+pub(crate) fn member_source(
+    manifest: &ConfigSource,
+    index: usize,
+    entry: &ServiceEntry,
+) -> Result<ConfigSource> {
+    match entry {
+        ServiceEntry::File { path, format } => {
+            let base = Path::new(&manifest.origin)
+                .parent()
+                .unwrap_or(Path::new(""));
+            ConfigSource::read(&base.join(path).to_string_lossy(), *format)
+        }
+        ServiceEntry::Inline(value) => Ok(ConfigSource::new(
+            format!("{} (services[{}])", manifest.origin, index),
+            serde_json::to_string_pretty(value)?,
+            ConfigFormat::Json,
+        )),
+    }
+}
+
+/// Settings the workspace needs from a member config, whatever its kind.
+#[derive(Deserialize)]
+pub(crate) struct MemberProbe {
+    /// Also the member's directory.
+    pub project_name: String,
+    pub templates_dir: Option<String>,
+    #[serde(default)]
+    pub post_generate: Vec<PostStep>,
+}
+
+/// Point validation problems of an inline service at its place in the manifest.
+fn relocate(manifest: &ConfigSource, index: usize, err: anyhow::Error) -> anyhow::Error {
+    match err.downcast::<ValidationError>() {
+        Ok(err) => {
+            let issues = err
+                .diagnostics
+                .into_iter()
+                .map(|d| Issue {
+                    path: format!("services[{}].{}", index, d.issue.path),
+                    ..d.issue
+                })
+                .collect();
+            ValidationError::located(manifest, issues).into()
+        }
+        Err(err) => err,
+    }
+}
+
+/// Registry dependencies the members can share through
+/// `[workspace.dependencies]`, from each member's `(name, Cargo.toml)`.
+///
+/// A dependency is shared when every member that uses it asks for the same
+/// version and default features; features stay in the members. Others are
+/// left to the members, with a note.
+pub fn shared_dependencies(
+    cargo_tomls: &[(String, String)],
+) -> Result<(BTreeMap<String, String>, Vec<String>)> {
+    // Dependency name -> spec -> members asking for it.
+    let mut specs: BTreeMap<String, BTreeMap<String, Vec<&str>>> = BTreeMap::new();
+    for (member, cargo_toml) in cargo_tomls {
+        let doc: DocumentMut = cargo_toml
+            .parse()
+            .with_context(|| format!("failed to parse Cargo.toml of {}", member))?;
+        let Some(deps) = doc
+            .get("dependencies")
+            .and_then(|deps| deps.as_table_like())
+        else {
+            continue;
+        };
+        for (name, item) in deps.iter() {
+            if let Some((spec, _)) = split_dependency(item) {
+                specs
+                    .entry(name.to_string())
+                    .or_default()
+                    .entry(spec)
+                    .or_default()
+                    .push(member);
+            }
+        }
+    }
+
+    let mut shared = BTreeMap::new();
+    let mut notes = Vec::new();
+    for (name, mut by_spec) in specs {
+        if by_spec.len() == 1 {
+            let (spec, _) = by_spec.pop_first().unwrap();
+            shared.insert(name, spec);
+            continue;
+        }
+        let asks: Vec<_> = by_spec
+            .iter()
+            .map(|(spec, members)| format!("{} asks for {}", members.join(", "), spec))
+            .collect();
+        notes.push(format!(
+            "{} is not shared through the workspace: {}",
+            name,
+            asks.join("; ")
+        ));
+    }
+    Ok((shared, notes))
+}
+
+/// Rewrite a member's `Cargo.toml` so the dependencies in `shared` (see
+/// [`shared_dependencies`]) use `workspace = true`.
+///
+/// Features and other member-specific keys stay in the member.
+pub fn inherit_dependencies(
+    member: &str,
+    cargo_toml: &str,
+    shared: &BTreeMap<String, String>,
+) -> Result<String> {
+    let mut doc: DocumentMut = cargo_toml
+        .parse()
+        .with_context(|| format!("failed to parse Cargo.toml of {}", member))?;
+    let Some(deps) = doc
+        .get_mut("dependencies")
+        .and_then(|deps| deps.as_table_like_mut())
+    else {
+        return Ok(cargo_toml.to_string());
+    };
+
+    for (name, item) in deps.iter_mut() {
+        let Some((spec, inherited)) = split_dependency(item) else {
+            continue;
+        };
+        if shared.get(name.get()) == Some(&spec) {
+            *item = Item::Value(Value::InlineTable(inherited));
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+/// Split a dependency into the part shared through the workspace (version and
+/// default features) and what stays in the member. `None` for path and git
+/// dependencies, which are left alone.
+fn split_dependency(item: &Item) -> Option<(String, InlineTable)> {
+    let mut inherited = InlineTable::new();
+    inherited.insert("workspace", true.into());
+
+    if let Some(version) = item.as_str() {
+        return Some((Value::from(version).to_string(), inherited));
+    }
+
+    let table = item.as_table_like()?;
+    if table.contains_key("path") || table.contains_key("git") || table.contains_key("workspace") {
+        return None;
+    }
+
+    let mut shared = InlineTable::new();
+    for (key, value) in table.iter() {
+        let Some(value) = value.as_value() else {
+            continue;
+        };
+        let mut value = value.clone();
+        value.decor_mut().clear();
+        match key {
+            "version" | "default-features" | "default_features" => {
+                shared.insert(key, value);
+            }
+            _ => {
+                inherited.insert(key, value);
+            }
+        }
+    }
+
+    let spec = match shared.get("version") {
+        Some(version) if shared.len() == 1 => version.to_string(),
+        _ => Value::InlineTable(shared).to_string(),
+    };
+    Some((spec, inherited))
+}
@@ -1,11 +1,37 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Child, Command};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
+use netgen::config::{ConfigFormat, ConfigSource};
+use netgen::connection::{
+    default_shutdown_grace, ConfigDuration, LimitsTemplateCtx, TimeoutsTemplateCtx,
+};
+use netgen::generator::{GenerateArgs, Generator, Registry, Rendered};
+use netgen::handler_merge::merge_handlers;
 use netgen::http_axum::{generate_http_axum_project, HttpAxumTemplateCtx, RouteTemplate};
-use netgen::read_mode::ReadModeTemplateCtx;
-use netgen::tcp_echo::{generate_tcp_echo_project, TcpEchoTemplateCtx};
-use netgen::tcp_worker::{generate_tcp_worker_project, TcpWorkerTemplateCtx};
+use netgen::lock::{lock_path, read_lock};
+use netgen::migrate::{migrate, migrate_with, Change, Edit, Step, CURRENT_VERSION, MIGRATIONS};
+use netgen::post_generate::{PostGenerateError, PostStep};
+use netgen::preview::{plan_changes, unified_diff, FileStatus};
+use netgen::read_mode::{ByteString, LengthField, LengthHeader, ReadModeTemplateCtx, YamlReadMode};
+use netgen::status::{project_status, FileState};
+use netgen::tcp_echo::{
+    generate_tcp_echo_project, generate_tcp_echo_project_with_templates, render_tcp_echo_project,
+    TcpEchoGenerator, TcpEchoTemplateCtx,
+};
+use netgen::tcp_worker::{
+    generate_tcp_worker_project, render_tcp_worker_project, TcpWorkerTemplateCtx,
+};
+use netgen::templates::TemplateSet;
+use netgen::validate::ValidationError;
+use netgen::workspace::{
+    generate_workspace, inherit_dependencies, shared_dependencies, workspace_schema,
+};
 
 /// Helper function to run cargo check on a generated project.
 fn cargo_check(project_dir: &Path) -> Result<(), String> {
@@ -27,6 +53,176 @@ fn cargo_check(project_dir: &Path) -> Result<(), String> {
     Ok(())
 }
 
+/// A YAML config, as read from `server.yaml`.
+fn yaml(source: impl Into<String>) -> ConfigSource {
+    ConfigSource::new("server.yaml", source.into(), ConfigFormat::Yaml)
+}
+
+/// Render `config` with the embedded templates, through the generator its
+/// `kind` picks.
+fn render(config: &ConfigSource) -> anyhow::Result<Rendered> {
+    Registry::builtin()
+        .for_config(config)?
+        .render_config(config, &TemplateSet::embedded())
+}
+
+/// The `src/main.rs` rendered from `config`.
+fn main_rs(config: &ConfigSource) -> String {
+    render(config).unwrap().files["src/main.rs"].clone()
+}
+
+/// A generated TCP server, built and listening on a free local port. It is
+/// killed when dropped.
+struct Server {
+    process: Child,
+    port: u16,
+    /// Lines the server logged, on stdout or stderr.
+    log: mpsc::Receiver<String>,
+    _dir: TempDir,
+}
+
+impl Server {
+    /// Generate, build and start project `name` from `config`, a YAML config
+    /// without `project_name` and `port` that logs with `tracing: false`.
+    fn start(name: &str, config: &str) -> Server {
+        let dir = TempDir::new().expect("Failed to create temp directory");
+        let project_dir = dir.path().join(name);
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Failed to find a free port")
+            .port();
+        let config = yaml(format!(
+            "project_name: {}\nport: {}\n{}",
+            name, port, config
+        ));
+        let args = GenerateArgs {
+            out_dir: Some(project_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        Registry::builtin()
+            .for_config(&config)
+            .unwrap()
+            .run(&config, &args)
+            .unwrap();
+
+        // One target directory for all servers, so dependencies build once.
+        let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("servers");
+        let output = Command::new("cargo")
+            .args(["build", "--quiet"])
+            .env("CARGO_TARGET_DIR", &target_dir)
+            .current_dir(&project_dir)
+            .output()
+            .expect("Failed to run cargo build");
+        assert!(
+            output.status.success(),
+            "cargo build failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        // stdout and stderr share a pipe, so the log keeps its order.
+        let (output, input) = std::io::pipe().expect("Failed to create a pipe");
+        let process = Command::new(target_dir.join("debug").join(name))
+            .stdout(input.try_clone().unwrap())
+            .stderr(input)
+            .spawn()
+            .expect("Failed to start the server");
+        let (tx, log) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines().map_while(Result::ok) {
+                let _ = tx.send(line);
+            }
+        });
+        let server = Server {
+            process,
+            port,
+            log,
+            _dir: dir,
+        };
+        server.wait_for_log("listening on");
+        server
+    }
+
+    /// Wait for the server to log a line containing `text`, and return it.
+    fn wait_for_log(&self, text: &str) -> String {
+        loop {
+            match self.log.recv_timeout(Duration::from_secs(10)) {
+                Ok(line) if line.contains(text) => return line,
+                Ok(_) => {}
+                Err(err) => panic!("server never logged `{}`: {}", text, err),
+            }
+        }
+    }
+
+    /// Open a connection; reads on it give up after 5 seconds.
+    fn connect(&self) -> TcpStream {
+        let stream = TcpStream::connect(("127.0.0.1", self.port)).expect("Failed to connect");
+        stream.set_nodelay(true).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    /// Send SIGTERM to the server.
+    #[cfg(unix)]
+    fn terminate(&self) {
+        let status = Command::new("kill")
+            .args(["-TERM", &self.process.id().to_string()])
+            .status()
+            .expect("Failed to run kill");
+        assert!(status.success());
+    }
+
+    /// Wait up to 10 seconds for the server to exit, and check it succeeded.
+    #[cfg(unix)]
+    fn wait_for_exit(&mut self) {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(10) {
+            if let Some(status) = self.process.try_wait().unwrap() {
+                assert!(status.success(), "server exited with {}", status);
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("server still running after 10 seconds");
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Write `parts` one by one, pausing in between so the server reads them
+/// separately.
+fn send_in_parts(stream: &mut TcpStream, parts: &[&[u8]]) {
+    for part in parts {
+        stream.write_all(part).unwrap();
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// The next `n` bytes received on `stream`.
+fn receive(stream: &mut TcpStream, n: usize) -> Vec<u8> {
+    let mut received = vec![0; n];
+    stream.read_exact(&mut received).unwrap();
+    received
+}
+
+/// Everything received on `stream` until the server closes it.
+fn receive_until_closed(stream: &mut TcpStream) -> Vec<u8> {
+    let mut received = Vec::new();
+    match stream.read_to_end(&mut received) {
+        Ok(_) => {}
+        // Closed with data of the client left unread.
+        Err(err) if err.kind() == ErrorKind::ConnectionReset => {}
+        Err(err) => panic!("connection not closed: {}", err),
+    }
+    received
+}
+
 #[test]
 fn test_tcp_echo_lines() {
     let temp_dir = TempDir::new().expect("Failed to create temp directory");
@@ -43,6 +239,9 @@ fn test_tcp_echo_lines() {
         port: 4000,
         tracing_enabled: false,
         read_mode,
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
     };
 
     generate_tcp_echo_project(&ctx, &project_dir).expect("Failed to generate TCP echo project");
@@ -66,6 +265,9 @@ fn test_tcp_echo_fixed_size() {
         port: 4001,
         tracing_enabled: true,
         read_mode,
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
     };
 
     generate_tcp_echo_project(&ctx, &project_dir).expect("Failed to generate TCP echo project");
@@ -79,7 +281,7 @@ fn test_tcp_echo_delimited() {
     let project_dir = temp_dir.path().join("test-echo-delimited");
     let read_mode = ReadModeTemplateCtx {
         is_delimited: true,
-        delim_byte: Some(10), // newline
+        delim_literal: Some(ByteString(vec![b'\n']).rust_literal()),
         delim_max_len: Some(65535),
         ..Default::default()
     };
@@ -89,6 +291,9 @@ fn test_tcp_echo_delimited() {
         port: 4002,
         tracing_enabled: false,
         read_mode,
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
     };
 
     generate_tcp_echo_project(&ctx, &project_dir).expect("Failed to generate TCP echo project");
@@ -103,12 +308,8 @@ fn test_tcp_echo_length_prefixed() {
 
     let read_mode = ReadModeTemplateCtx {
         is_length_prefixed: true,
-        lp_len_bytes: Some(2),
-        lp_big_endian: Some(true),
+        length_header: Some(LengthHeader::from(LengthField::new(2, false, true).unwrap()).into()),
         lp_max_len: Some(65535),
-        lp_parse_len_code:
-            "let frame_len: usize = u16::from_be_bytes([len_buf[0], len_buf[1]]) as usize;"
-                .to_string(),
         ..Default::default()
     };
 
@@ -117,6 +318,9 @@ fn test_tcp_echo_length_prefixed() {
         port: 4003,
         tracing_enabled: true,
         read_mode,
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
     };
 
     generate_tcp_echo_project(&ctx, &project_dir).expect("Failed to generate TCP echo project");
@@ -142,6 +346,9 @@ fn test_tcp_worker_lines() {
         workers: 4,
         event_buffer: 1024,
         read_mode,
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
     };
 
     generate_tcp_worker_project(&ctx, &project_dir).expect("Failed to generate TCP worker project");
@@ -167,8 +374,42 @@ fn test_tcp_worker_fixed_size() {
         workers: 2,
         event_buffer: 512,
         read_mode,
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
+    };
+
+    generate_tcp_worker_project(&ctx, &project_dir).expect("Failed to generate TCP worker project");
+
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_tcp_worker_varint_prefixed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-varint");
+
+    let read_mode = ReadModeTemplateCtx {
+        is_varint_prefixed: true,
+        varint_max_len: Some(65535),
+        ..Default::default()
+    };
+
+    let ctx = TcpWorkerTemplateCtx {
+        project_name: "test-worker-varint".to_string(),
+        port: 5000,
+        tracing_enabled: false,
+        workers: 2,
+        event_buffer: 64,
+        read_mode,
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
     };
 
+    let files = render_tcp_worker_project(&ctx).unwrap();
+    assert!(files["src/main.rs"].contains("overlong varint length encoding"));
+
     generate_tcp_worker_project(&ctx, &project_dir).expect("Failed to generate TCP worker project");
 
     cargo_check(&project_dir).expect("Generated project failed to compile");
@@ -202,6 +443,7 @@ fn test_http_axum_basic() {
         db_enabled: false,
         db_url_env: None,
         db_max_connections: None,
+        shutdown_grace: default_shutdown_grace().rust_expr(),
     };
 
     generate_http_axum_project(&ctx, &project_dir).expect("Failed to generate HTTP axum project");
@@ -237,6 +479,7 @@ fn test_http_axum_with_database() {
         db_enabled: true,
         db_url_env: Some("DATABASE_URL".to_string()),
         db_max_connections: Some(10),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
     };
 
     generate_http_axum_project(&ctx, &project_dir).expect("Failed to generate HTTP axum project");
@@ -288,9 +531,1738 @@ fn test_http_axum_multiple_methods() {
         db_enabled: false,
         db_url_env: None,
         db_max_connections: None,
+        shutdown_grace: default_shutdown_grace().rust_expr(),
     };
 
     generate_http_axum_project(&ctx, &project_dir).expect("Failed to generate HTTP axum project");
 
     cargo_check(&project_dir).expect("Generated project failed to compile");
 }
+
+#[test]
+fn test_template_overrides_fall_back_to_embedded() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let templates_dir = temp_dir.path().join("templates");
+    let project_dir = temp_dir.path().join("test-echo-override");
+
+    std::fs::create_dir_all(templates_dir.join("tcp_echo")).unwrap();
+    std::fs::write(
+        templates_dir.join("tcp_echo/main.rs.hbs"),
+        "// custom main for {{project_name}}\nfn main() {}\n",
+    )
+    .unwrap();
+
+    let ctx = TcpEchoTemplateCtx {
+        project_name: "test-echo-override".to_string(),
+        port: 4000,
+        tracing_enabled: false,
+        read_mode: ReadModeTemplateCtx {
+            is_lines: true,
+            ..Default::default()
+        },
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
+    };
+
+    let templates = TemplateSet::with_overrides(&templates_dir);
+    let overrides = generate_tcp_echo_project_with_templates(&ctx, &project_dir, &templates)
+        .expect("Failed to generate TCP echo project");
+
+    assert_eq!(overrides, vec![templates_dir.join("tcp_echo/main.rs.hbs")]);
+
+    let main_rs = std::fs::read_to_string(project_dir.join("src/main.rs")).unwrap();
+    assert!(main_rs.starts_with("// custom main for test-echo-override"));
+
+    // Cargo.toml has no override and comes from the embedded template.
+    let cargo_toml = std::fs::read_to_string(project_dir.join("Cargo.toml")).unwrap();
+    assert!(cargo_toml.contains("name = \"test-echo-override\""));
+}
+
+#[test]
+fn test_registry_runs_generators_by_name() {
+    let registry = Registry::builtin();
+    let names: Vec<_> = registry.iter().map(|g| g.name()).collect();
+    assert_eq!(names, vec!["tcp-echo", "tcp-worker", "http-axum"]);
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-registry");
+
+    let config = ConfigSource::new(
+        "test",
+        r#"
+project_name: test-worker-registry
+port: 5000
+tracing: false
+workers: 2
+event_buffer: 16
+read_mode:
+  type: fixed_size
+  frame_size: 64
+"#,
+        ConfigFormat::Yaml,
+    );
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let out_dir = registry
+        .run("tcp-worker", &config, &args)
+        .expect("Failed to generate TCP worker project");
+    assert_eq!(out_dir, project_dir);
+
+    let generator = registry.get("tcp-worker").unwrap();
+    for file in generator.files() {
+        assert!(
+            project_dir.join(file.path).is_file(),
+            "missing {}",
+            file.path
+        );
+    }
+
+    assert!(registry.run("udp-echo", &config, &args).is_err());
+}
+
+#[test]
+fn test_dry_run_plan_and_diff() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-echo-diff");
+
+    let mut ctx = TcpEchoTemplateCtx {
+        project_name: "test-echo-diff".to_string(),
+        port: 4000,
+        tracing_enabled: false,
+        read_mode: ReadModeTemplateCtx {
+            is_lines: true,
+            max_line_len: Some(100),
+            ..Default::default()
+        },
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
+    };
+
+    let templates = TemplateSet::embedded();
+    let rendered = TcpEchoGenerator.render(&ctx, &templates).unwrap();
+    let changes = plan_changes(&project_dir, &rendered.files).unwrap();
+    assert!(changes.iter().all(|c| c.status == FileStatus::Create));
+
+    generate_tcp_echo_project(&ctx, &project_dir).expect("Failed to generate TCP echo project");
+
+    ctx.read_mode.max_line_len = Some(200);
+    let rendered = TcpEchoGenerator.render(&ctx, &templates).unwrap();
+    let changes = plan_changes(&project_dir, &rendered.files).unwrap();
+
+    let statuses: Vec<_> = changes
+        .iter()
+        .map(|c| (c.path.to_string_lossy().into_owned(), c.status))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ("Cargo.toml".to_string(), FileStatus::Unchanged),
+            ("src/main.rs".to_string(), FileStatus::Update),
+        ]
+    );
+
+    let diff = unified_diff(&changes[1]).unwrap();
+    assert!(diff.starts_with("--- a/src/main.rs\n+++ b/src/main.rs\n"));
+    assert!(diff.contains("-const MAX_LINE_LEN: usize = 100;"));
+    assert!(diff.contains("+const MAX_LINE_LEN: usize = 200;"));
+    assert!(unified_diff(&changes[0]).is_none());
+
+    // Planning never touches the files on disk.
+    let main_rs = std::fs::read_to_string(project_dir.join("src/main.rs")).unwrap();
+    assert!(main_rs.contains("const MAX_LINE_LEN: usize = 100;"));
+}
+
+#[test]
+fn test_http_axum_regeneration_keeps_handler_bodies() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-axum-merge");
+
+    let route = |path: &str, handler: &str| RouteTemplate {
+        path: path.to_string(),
+        method_fn: "get".to_string(),
+        handler_name: handler.to_string(),
+        response: format!("{} response", handler),
+    };
+
+    let mut ctx = HttpAxumTemplateCtx {
+        project_name: "test-axum-merge".to_string(),
+        port: 3003,
+        tracing_enabled: false,
+        routes: vec![route("/", "root"), route("/old", "old_route")],
+        db_enabled: false,
+        db_url_env: None,
+        db_max_connections: None,
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+    };
+
+    generate_http_axum_project(&ctx, &project_dir).expect("Failed to generate HTTP axum project");
+
+    // Hand-edit the body of `root`, including braces inside strings and comments.
+    let handlers_path = project_dir.join("src/handlers.rs");
+    let handlers = std::fs::read_to_string(&handlers_path).unwrap();
+    let handlers = handlers.replace(
+        "    \"root response\"\n",
+        "    // custom body }\n    let greeting = format!(\"{}\", \"hi }\");\n    greeting\n",
+    );
+    std::fs::write(&handlers_path, handlers).unwrap();
+
+    ctx.routes = vec![route("/", "root"), route("/new", "new_route")];
+    generate_http_axum_project(&ctx, &project_dir).expect("Failed to regenerate HTTP axum project");
+
+    let handlers = std::fs::read_to_string(&handlers_path).unwrap();
+    assert!(handlers.contains("let greeting = format!(\"{}\", \"hi }\");"));
+    assert!(!handlers.contains("\"root response\""));
+    assert!(handlers.contains("pub async fn new_route()"));
+    // Handlers dropped from the config are kept, not deleted.
+    assert!(handlers.contains("pub async fn old_route()"));
+
+    let merge = merge_handlers(&handlers, &handlers, &[]);
+    assert_eq!(merge.contents, handlers);
+    assert_eq!(merge.kept, vec!["root", "new_route", "old_route"]);
+
+    // Imports and helper items added by hand are kept too.
+    let handlers = handlers
+        .replace(
+            "use axum::response::IntoResponse;\n",
+            "use axum::response::IntoResponse;\nuse std::collections::HashMap;\n",
+        )
+        .replace(
+            "    greeting\n",
+            "    let counts: HashMap<&str, usize> = HashMap::new();\n    format!(\"{}{}{}\", greeting, counts.len(), Helper::new().0)\n",
+        )
+        + "\n/// Hand-written helper.\nstruct Helper(usize);\n\nimpl Helper {\n    fn new() -> Self {\n        Helper(LIMIT)\n    }\n}\n\nconst LIMIT: usize = 3;\n";
+    std::fs::write(&handlers_path, &handlers).unwrap();
+    ctx.routes.push(route("/extra", "extra"));
+    generate_http_axum_project(&ctx, &project_dir).expect("Failed to regenerate HTTP axum project");
+
+    let regenerated = std::fs::read_to_string(&handlers_path).unwrap();
+    assert!(regenerated
+        .starts_with("use axum::response::IntoResponse;\nuse std::collections::HashMap;\n\n"));
+    assert!(regenerated.contains("/// Hand-written helper.\nstruct Helper(usize);"));
+    assert!(regenerated.contains("impl Helper {\n    fn new() -> Self {"));
+    assert!(regenerated.contains("const LIMIT: usize = 3;"));
+    assert!(regenerated.contains("pub async fn extra()"));
+    let merge = merge_handlers(&regenerated, &regenerated, &[]);
+    assert_eq!(merge.contents, regenerated);
+    assert!(merge.carried.is_empty());
+
+    // Imports the template writes for a database are its own to remove.
+    ctx.db_enabled = true;
+    ctx.db_url_env = Some("DATABASE_URL".to_string());
+    generate_http_axum_project(&ctx, &project_dir).expect("Failed to regenerate HTTP axum project");
+    assert!(std::fs::read_to_string(&handlers_path)
+        .unwrap()
+        .contains("use crate::AppState;"));
+    ctx.db_enabled = false;
+    ctx.db_url_env = None;
+    generate_http_axum_project(&ctx, &project_dir).expect("Failed to regenerate HTTP axum project");
+    let handlers = std::fs::read_to_string(&handlers_path).unwrap();
+    assert_eq!(handlers, regenerated);
+
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_refuses_to_overwrite_foreign_directory() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("hand-written");
+    std::fs::create_dir_all(&project_dir).unwrap();
+    std::fs::write(project_dir.join("Cargo.toml"), "# hand-written\n").unwrap();
+
+    let registry = Registry::builtin();
+    let config = ConfigSource::new(
+        "test",
+        "project_name: test-echo-guard\nport: 4000\ntracing: false\nread_mode:\n  type: lines\n",
+        ConfigFormat::Yaml,
+    );
+    let mut args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let err = registry.run("tcp-echo", &config, &args).unwrap_err();
+    assert!(err.to_string().contains("not generated by netgen"));
+    assert_eq!(
+        std::fs::read_to_string(project_dir.join("Cargo.toml")).unwrap(),
+        "# hand-written\n"
+    );
+
+    // Nothing is moved aside when rendering fails.
+    let broken = temp_dir.path().join("broken-templates");
+    std::fs::create_dir_all(broken.join("tcp_echo")).unwrap();
+    std::fs::write(broken.join("tcp_echo/main.rs.hbs"), "{{#if}}\n").unwrap();
+    args.backup = true;
+    args.templates = Some(broken.to_string_lossy().into_owned());
+    let err = registry.run("tcp-echo", &config, &args).unwrap_err();
+    assert!(format!("{:#}", err).contains("failed to parse template tcp_echo/main.rs.hbs"));
+    assert!(project_dir.join("Cargo.toml").is_file());
+    let backup_dir = temp_dir.path().join("hand-written.bak");
+    assert!(!backup_dir.exists());
+
+    args.templates = None;
+    registry.run("tcp-echo", &config, &args).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(backup_dir.join("Cargo.toml")).unwrap(),
+        "# hand-written\n"
+    );
+    assert!(lock_path(&project_dir).is_file());
+
+    // A netgen project can be regenerated without --force.
+    args.backup = false;
+    registry.run("tcp-echo", &config, &args).unwrap();
+
+    std::fs::write(backup_dir.join("notes.txt"), "mine").unwrap();
+    args.out_dir = Some(backup_dir.to_string_lossy().into_owned());
+    args.force = true;
+    registry.run("tcp-echo", &config, &args).unwrap();
+    assert!(backup_dir.join("notes.txt").is_file());
+    assert!(backup_dir.join("src/main.rs").is_file());
+}
+
+#[test]
+fn test_validation_reports_every_problem_with_location() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-axum-invalid");
+
+    let config = ConfigSource::new(
+        "http.yaml",
+        r#"project_name: my service
+port: 3000
+tracing: false
+routes:
+  - path: /
+    method: GET
+    handler: root
+    response: "Hello"
+  - path: /health
+    method: GET
+    handler: root
+    response: "OK"
+  - path: /items
+    method: POST
+    handler: create-item
+    response: "Created"
+"#,
+        ConfigFormat::Yaml,
+    );
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let err = Registry::builtin()
+        .run("http-axum", &config, &args)
+        .unwrap_err();
+    let err = err
+        .downcast_ref::<ValidationError>()
+        .expect("expected a validation error");
+
+    let found: Vec<_> = err
+        .diagnostics
+        .iter()
+        .map(|d| (d.issue.path.as_str(), d.line, d.column))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("project_name", Some(1), Some(1)),
+            ("routes[1].handler", Some(11), Some(5)),
+            ("routes[2].handler", Some(15), Some(5)),
+        ]
+    );
+    assert_eq!(
+        err.diagnostics[2].issue.suggestion.as_deref(),
+        Some("handler: create_item")
+    );
+    assert!(err
+        .to_string()
+        .contains("http.yaml:11:5: routes[1].handler"));
+
+    // Nothing is written when validation fails.
+    assert!(!project_dir.exists());
+}
+
+#[test]
+fn test_config_schemas() {
+    let registry = Registry::builtin();
+
+    let echo = serde_json::to_value(registry.require("tcp-echo").unwrap().config_schema()).unwrap();
+    assert_eq!(echo["title"], "TcpEchoYamlConfig");
+    assert!(echo["required"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("read_mode")));
+
+    let modes: Vec<_> = echo["$defs"]["YamlReadMode"]["oneOf"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|variant| variant["properties"]["type"]["const"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        modes,
+        vec![
+            "lines",
+            "fixed_size",
+            "delimited",
+            "length_prefixed",
+            "varint_prefixed"
+        ]
+    );
+
+    let axum =
+        serde_json::to_value(registry.require("http-axum").unwrap().config_schema()).unwrap();
+    assert!(axum["$defs"]["DatabaseYamlConfig"].is_object());
+    assert!(!axum["$defs"]["DatabaseYamlConfig"]["required"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("kind")));
+
+    assert!(registry.require("udp-echo").is_err());
+
+    // `netgen schema` also takes the `kind` written in configs.
+    assert_eq!(
+        registry.for_kind("tcp_worker").unwrap().name(),
+        "tcp-worker"
+    );
+    assert!(registry.for_kind("tcp-worker").is_none());
+
+    let workspace = serde_json::to_value(workspace_schema()).unwrap();
+    assert_eq!(workspace["title"], "WorkspaceManifest");
+    assert_eq!(workspace["properties"]["kind"]["const"], "workspace");
+    assert!(workspace["properties"]["services"].is_object());
+}
+
+#[test]
+fn test_toml_and_json_configs() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let registry = Registry::builtin();
+
+    let toml_path = temp_dir.path().join("worker.toml");
+    std::fs::write(
+        &toml_path,
+        r#"project_name = "test-worker-toml"
+port = 5001
+tracing = false
+workers = 2
+event_buffer = 16
+
+[read_mode]
+type = "delimited"
+delim = 10
+max_len = 1024
+"#,
+    )
+    .unwrap();
+    let json_path = temp_dir.path().join("echo.json");
+    std::fs::write(
+        &json_path,
+        r#"{
+  "project_name": "test-echo-json",
+  "port": 4001,
+  "tracing": false,
+  "read_mode": { "type": "lines", "max_line_len": 256 }
+}
+"#,
+    )
+    .unwrap();
+
+    for (name, path, project) in [
+        ("tcp-worker", &toml_path, "test-worker-toml"),
+        ("tcp-echo", &json_path, "test-echo-json"),
+    ] {
+        let config = ConfigSource::read(path.to_str().unwrap(), None).unwrap();
+        let args = GenerateArgs {
+            out_dir: Some(temp_dir.path().join(project).to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let out_dir = registry.run(name, &config, &args).unwrap();
+        let cargo_toml = std::fs::read_to_string(out_dir.join("Cargo.toml")).unwrap();
+        assert!(cargo_toml.contains(&format!("name = \"{}\"", project)));
+    }
+
+    // Parse errors look the same regardless of the format.
+    for (origin, text, format, location) in [
+        (
+            "bad.yaml",
+            "port: [1\n",
+            ConfigFormat::Yaml,
+            "bad.yaml as YAML: 2:1: ",
+        ),
+        (
+            "bad.toml",
+            "port = [1\n",
+            ConfigFormat::Toml,
+            "bad.toml as TOML: 1:",
+        ),
+        (
+            "bad.json",
+            "{\"port\": [1\n",
+            ConfigFormat::Json,
+            "bad.json as JSON: 2:0: ",
+        ),
+    ] {
+        let err = ConfigSource::new(origin, text, format)
+            .parse::<serde_json::Value>()
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with(&format!("failed to parse {}", location)),
+            "{}",
+            err
+        );
+    }
+
+    assert!(ConfigSource::read("config.ini", None)
+        .unwrap_err()
+        .to_string()
+        .contains("--format"));
+
+    // Validation problems are located in TOML files too.
+    let config = ConfigSource::new(
+        "worker.toml",
+        "project_name = \"test worker\"\nport = 5000\ntracing = false\nworkers = 0\nevent_buffer = 16\n\n[read_mode]\ntype = \"lines\"\n",
+        ConfigFormat::Toml,
+    );
+    let err = registry
+        .run("tcp-worker", &config, &GenerateArgs::default())
+        .unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    let found: Vec<_> = err
+        .diagnostics
+        .iter()
+        .map(|d| (d.issue.path.as_str(), d.line, d.column))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("project_name", Some(1), Some(1)),
+            ("workers", Some(4), Some(1)),
+        ]
+    );
+}
+
+#[test]
+fn test_generate_picks_generator_from_kind() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-echo-kind");
+    let registry = Registry::builtin();
+
+    let config = ConfigSource::new(
+        "echo.yaml",
+        "kind: tcp_echo\nproject_name: test-echo-kind\nport: 4000\ntracing: false\nread_mode:\n  type: lines\n",
+        ConfigFormat::Yaml,
+    );
+    let generator = registry.for_config(&config).unwrap();
+    assert_eq!(generator.name(), "tcp-echo");
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    generator.run(&config, &args).unwrap();
+    assert!(project_dir.join("src/main.rs").is_file());
+
+    // A subcommand refuses a config written for another generator.
+    let err = registry.run("tcp-worker", &config, &args).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    assert_eq!(err.diagnostics[0].issue.path, "kind");
+    assert_eq!(err.diagnostics[0].line, Some(1));
+
+    let unknown = ConfigSource::new("x.yaml", "kind: udp_echo\n", ConfigFormat::Yaml);
+    let err = registry.for_config(&unknown).err().unwrap().to_string();
+    assert!(err.contains("unknown kind `udp_echo`"));
+    assert!(err.contains("kind: one of tcp_echo, tcp_worker, http_axum"));
+
+    let missing = ConfigSource::new("x.yaml", "port: 4000\n", ConfigFormat::Yaml);
+    assert!(registry
+        .for_config(&missing)
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("missing `kind` key"));
+
+    let schema =
+        serde_json::to_value(registry.require("http-axum").unwrap().config_schema()).unwrap();
+    assert_eq!(schema["properties"]["kind"]["const"], "http_axum");
+}
+
+#[test]
+fn test_workspace_manifest() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let workspace_dir = temp_dir.path().join("test-platform");
+
+    std::fs::write(
+        temp_dir.path().join("ingest.yaml"),
+        r#"kind: tcp_worker
+project_name: ingest
+port: 5000
+tracing: true
+workers: 2
+event_buffer: 64
+read_mode:
+  type: lines
+"#,
+    )
+    .unwrap();
+    let manifest_path = temp_dir.path().join("platform.yaml");
+    std::fs::write(
+        &manifest_path,
+        r#"kind: workspace
+name: test-platform
+services:
+  - path: ingest.yaml
+  - kind: http_axum
+    project_name: api
+    port: 3000
+    tracing: false
+    routes:
+      - path: /
+        method: GET
+        handler: root
+        response: "Hello"
+"#,
+    )
+    .unwrap();
+
+    let manifest = ConfigSource::read(manifest_path.to_str().unwrap(), None).unwrap();
+    let args = GenerateArgs {
+        out_dir: Some(workspace_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    generate_workspace(&Registry::builtin(), &manifest, &args).unwrap();
+
+    let root = std::fs::read_to_string(workspace_dir.join("Cargo.toml")).unwrap();
+    assert!(root.contains("\"ingest\",\n    \"api\","));
+    assert!(root.contains("[workspace.dependencies]\nanyhow = \"1\"\naxum = \"0.7\"\n"));
+    assert!(root.contains("tokio = \"1\"\n"));
+    let api = std::fs::read_to_string(workspace_dir.join("api/Cargo.toml")).unwrap();
+    assert!(api.contains("tokio = { workspace = true, features = [\"full\"] }"));
+    assert!(api.contains("axum = { workspace = true }"));
+    assert!(workspace_dir.join("ingest/src/main.rs").is_file());
+
+    cargo_check(&workspace_dir).expect("Generated workspace failed to compile");
+
+    // Problems in inline services point into the manifest.
+    let manifest = ConfigSource::new(
+        "platform.yaml",
+        "name: broken\nservices:\n  - kind: tcp_echo\n    project_name: echo\n    port: 4000\n    tracing: false\n    read_mode:\n      type: fixed_size\n      frame_size: 0\n",
+        ConfigFormat::Yaml,
+    );
+    let err =
+        generate_workspace(&Registry::builtin(), &manifest, &GenerateArgs::default()).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    assert_eq!(
+        err.diagnostics[0].issue.path,
+        "services[0].read_mode.frame_size"
+    );
+    assert_eq!(err.diagnostics[0].line, Some(9));
+
+    // A broken member keeps the members before it from being written.
+    let broken_dir = temp_dir.path().join("test-broken");
+    let manifest = ConfigSource::new(
+        "platform.yaml",
+        "name: test-broken\nservices:\n  - kind: tcp_echo\n    project_name: echo\n    port: 4000\n    tracing: false\n    read_mode:\n      type: lines\n  - kind: tcp_echo\n    project_name: echo-fixed\n    port: 4001\n    tracing: false\n    read_mode:\n      type: fixed_size\n      frame_size: 0\n",
+        ConfigFormat::Yaml,
+    );
+    let args = GenerateArgs {
+        out_dir: Some(broken_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    assert!(generate_workspace(&Registry::builtin(), &manifest, &args).is_err());
+    assert!(!broken_dir.exists());
+}
+
+#[test]
+fn test_workspace_shared_dependencies() {
+    let members = [
+        (
+            "api".to_string(),
+            "[package]\nname = \"api\"\n\n[dependencies]\ntokio = { version = \"1\", features = [\"full\"] }\nserde = \"1\"\n".to_string(),
+        ),
+        (
+            "ingest".to_string(),
+            "[package]\nname = \"ingest\"\n\n[dependencies]\ntokio = { version = \"1\", features = [\"net\"] }\nserde = { version = \"1\", default-features = false }\n".to_string(),
+        ),
+    ];
+    let (shared, notes) = shared_dependencies(&members).unwrap();
+
+    // Features stay in the members, so they don't keep tokio from being shared.
+    assert_eq!(shared.keys().collect::<Vec<_>>(), ["tokio"]);
+    assert_eq!(
+        notes,
+        ["serde is not shared through the workspace: api asks for \"1\"; ingest asks for { version = \"1\", default-features = false }"]
+    );
+
+    let ingest = inherit_dependencies("ingest", &members[1].1, &shared).unwrap();
+    assert!(ingest.contains("tokio = { workspace = true, features = [\"net\"] }"));
+    assert!(ingest.contains("serde = { version = \"1\", default-features = false }"));
+}
+
+#[test]
+fn test_render_in_memory() {
+    let ctx = TcpEchoTemplateCtx {
+        project_name: "test-echo-memory".to_string(),
+        port: 4005,
+        tracing_enabled: false,
+        read_mode: ReadModeTemplateCtx {
+            is_lines: true,
+            max_line_len: Some(100),
+            ..Default::default()
+        },
+        timeouts: TimeoutsTemplateCtx::default(),
+        shutdown_grace: default_shutdown_grace().rust_expr(),
+        limits: LimitsTemplateCtx::default(),
+    };
+    let files = render_tcp_echo_project(&ctx).unwrap();
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["Cargo.toml", "src/main.rs"]
+    );
+    assert!(files["Cargo.toml"].contains("name = \"test-echo-memory\""));
+    assert!(files["src/main.rs"].contains("4005"));
+
+    let config = ConfigSource::new(
+        "http.yaml",
+        r#"kind: http_axum
+project_name: test-axum-memory
+port: 3000
+tracing: false
+routes:
+  - path: /
+    method: GET
+    handler: root
+    response: "Hello"
+"#,
+        ConfigFormat::Yaml,
+    );
+    let rendered = Registry::builtin()
+        .require("http-axum")
+        .unwrap()
+        .render_config(&config, &TemplateSet::embedded())
+        .unwrap();
+    assert!(rendered.files["src/handlers.rs"].contains("pub async fn root()"));
+    assert!(!Path::new("test-axum-memory").exists());
+}
+
+#[test]
+fn test_post_generate_steps() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let registry = Registry::builtin();
+    let config = |name: &str| {
+        ConfigSource::new(
+            format!("{}.yaml", name),
+            format!(
+                "project_name: {}\nport: 4000\ntracing: true\nread_mode:\n  type: lines\npost_generate: [fmt, check]\n",
+                name
+            ),
+            ConfigFormat::Yaml,
+        )
+    };
+    let args = |name: &str| GenerateArgs {
+        out_dir: Some(temp_dir.path().join(name).to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let out_dir = registry
+        .run("tcp-echo", &config("test-echo-fmt"), &args("test-echo-fmt"))
+        .unwrap();
+    let status = Command::new("rustfmt")
+        .args(["--check", "--edition", "2021", "src/main.rs"])
+        .current_dir(&out_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "src/main.rs is not formatted");
+
+    // Failures name the config and the template behind the broken file.
+    let templates_dir = temp_dir.path().join("templates");
+    std::fs::create_dir_all(templates_dir.join("tcp_echo")).unwrap();
+    let template = templates_dir.join("tcp_echo/main.rs.hbs");
+    std::fs::write(
+        &template,
+        "fn main() {\n    let port: u16 = \"{{port}}\";\n}\n",
+    )
+    .unwrap();
+    let mut check_args = args("test-echo-broken");
+    check_args.templates = Some(templates_dir.to_string_lossy().into_owned());
+
+    let err = registry
+        .run("tcp-echo", &config("test-echo-broken"), &check_args)
+        .unwrap_err();
+    let err = err.downcast_ref::<PostGenerateError>().unwrap();
+    assert_eq!(err.step, PostStep::Check);
+    assert_eq!(err.config.as_deref(), Some("test-echo-broken.yaml"));
+    assert_eq!(err.files.len(), 1);
+    assert_eq!(err.files[0].path, "src/main.rs");
+    assert_eq!(err.files[0].template, template.to_string_lossy());
+    assert!(err.output.contains("src/main.rs:2:"));
+
+    std::fs::write(&template, "fn main() {\n    let = ;\n}\n").unwrap();
+    check_args.out_dir = Some(
+        temp_dir
+            .path()
+            .join("test-echo-unparsable")
+            .to_string_lossy()
+            .into_owned(),
+    );
+    let err = registry
+        .run("tcp-echo", &config("test-echo-unparsable"), &check_args)
+        .unwrap_err();
+    let err = err.downcast_ref::<PostGenerateError>().unwrap();
+    assert_eq!(err.step, PostStep::Fmt);
+    assert!(err.output.contains("src/main.rs"));
+    assert!(!temp_dir.path().join("test-echo-unparsable").exists());
+}
+
+#[test]
+fn test_lockfile_and_status() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-axum-status");
+    let registry = Registry::builtin();
+
+    let config_text = r#"kind: http_axum
+project_name: test-axum-status
+port: 3000
+tracing: false
+routes:
+  - path: /
+    method: GET
+    handler: root
+    response: "Hello"
+"#;
+    let config = ConfigSource::new("http.yaml", config_text, ConfigFormat::Yaml);
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    registry.run("http-axum", &config, &args).unwrap();
+
+    let lock = read_lock(&project_dir).unwrap();
+    assert_eq!(lock.generator, "http-axum");
+    assert_eq!(lock.netgen_version, env!("CARGO_PKG_VERSION"));
+    assert!(lock.config_hash.starts_with("sha256:"));
+    assert_eq!(
+        lock.templates.checksums.keys().collect::<Vec<_>>(),
+        vec![
+            "http_axum/Cargo.toml.hbs",
+            "http_axum/handlers.rs.hbs",
+            "http_axum/main.rs.hbs",
+            "shutdown/signal.rs.hbs"
+        ]
+    );
+    assert_eq!(
+        lock.files.keys().collect::<Vec<_>>(),
+        vec!["Cargo.toml", "src/handlers.rs", "src/main.rs"]
+    );
+
+    let status = |config: &ConfigSource| {
+        let mut statuses = project_status(&registry, config, Some(&project_dir)).unwrap();
+        assert_eq!(statuses.len(), 1);
+        statuses.remove(0)
+    };
+
+    let fresh = status(&config);
+    assert!(!fresh.is_stale());
+    assert_eq!(fresh.touched_files().count(), 0);
+
+    let handlers = project_dir.join("src/handlers.rs");
+    let edited = std::fs::read_to_string(&handlers)
+        .unwrap()
+        .replace("\"Hello\"", "\"Hi\"");
+    std::fs::write(&handlers, edited).unwrap();
+    std::fs::remove_file(project_dir.join("src/main.rs")).unwrap();
+
+    let changed = ConfigSource::new(
+        "http.yaml",
+        config_text.replace("port: 3000", "port: 3001"),
+        ConfigFormat::Yaml,
+    );
+    let stale = status(&changed);
+    assert!(stale.config_changed);
+    assert!(stale.changed_templates.is_empty());
+    assert_eq!(
+        stale.touched_files().collect::<Vec<_>>(),
+        vec![
+            ("src/handlers.rs", FileState::Modified),
+            ("src/main.rs", FileState::Missing)
+        ]
+    );
+
+    assert!(project_status(&registry, &config, Some(temp_dir.path()))
+        .unwrap_err()
+        .to_string()
+        .contains("lock.json"));
+}
+
+#[test]
+fn test_migrate_adds_version_and_keeps_comments() {
+    let templates = TemplateSet::embedded();
+    let registry = Registry::builtin();
+
+    let yaml = ConfigSource::new(
+        "echo.yaml",
+        "# Echo service\nkind: tcp_echo  # picked by netgen generate\nproject_name: test-echo-migrate\nport: 4000\ntracing: false\nread_mode:\n  type: lines\n",
+        ConfigFormat::Yaml,
+    );
+    let migration = migrate(&yaml).unwrap();
+    assert_eq!(migration.from, 0);
+    assert_eq!(migration.edits.len(), 1);
+    assert!(migration.edits[0]
+        .to_string()
+        .starts_with("set `version` to 1"));
+    assert!(!migration.rewritten);
+    assert!(migration
+        .text
+        .starts_with("# Echo service\nversion: 1\nkind: tcp_echo  # picked by netgen generate\n"));
+
+    // Unversioned and migrated configs generate the same project.
+    let migrated = ConfigSource::new("echo.yaml", migration.text, ConfigFormat::Yaml);
+    let generator = registry.for_config(&migrated).unwrap();
+    assert_eq!(
+        generator.render_config(&yaml, &templates).unwrap().files,
+        generator
+            .render_config(&migrated, &templates)
+            .unwrap()
+            .files
+    );
+    assert!(migrate(&migrated).unwrap().edits.is_empty());
+
+    let toml = ConfigSource::new(
+        "worker.toml",
+        "# Worker service\nkind = \"tcp_worker\"\nproject_name = \"w\" # name\n\n[read_mode]\ntype = \"lines\"\n",
+        ConfigFormat::Toml,
+    );
+    let migration = migrate(&toml).unwrap();
+    assert!(!migration.rewritten);
+    assert!(migration.text.starts_with(
+        "# Worker service\nversion = 1\nkind = \"tcp_worker\"\nproject_name = \"w\" # name\n"
+    ));
+
+    let future = ConfigSource::new(
+        "echo.yaml",
+        "version: 99\nkind: tcp_echo\nproject_name: x\nport: 4000\ntracing: false\nread_mode:\n  type: lines\n",
+        ConfigFormat::Yaml,
+    );
+    let err = generator.render_config(&future, &templates).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    assert_eq!(err.diagnostics[0].issue.path, "version");
+    assert_eq!(err.diagnostics[0].line, Some(1));
+
+    let schema = serde_json::to_value(generator.config_schema()).unwrap();
+    assert_eq!(schema["properties"]["version"]["maximum"], CURRENT_VERSION);
+}
+
+#[test]
+fn test_migrate_renames_and_removes_fields() {
+    // A version 2 that renames `port` and `read_mode.type` and drops `tracing`.
+    fn to_v2(config: &serde_json::Value) -> Vec<Edit> {
+        let mut edits = vec![
+            Edit {
+                change: Change::Rename {
+                    path: vec!["port".to_string()],
+                    to: "listen_port".to_string(),
+                },
+                reason: "ports are named after what they do".to_string(),
+            },
+            Edit {
+                change: Change::Rename {
+                    path: vec!["read_mode".to_string(), "type".to_string()],
+                    to: "kind".to_string(),
+                },
+                reason: "read modes are picked by `kind`".to_string(),
+            },
+        ];
+        if config.get("tracing").is_some() {
+            edits.push(Edit {
+                change: Change::Remove {
+                    path: vec!["tracing".to_string()],
+                },
+                reason: "tracing is always on".to_string(),
+            });
+        }
+        edits
+    }
+    let steps: [Step; 2] = [MIGRATIONS[0], to_v2];
+
+    let yaml = ConfigSource::new(
+        "echo.yaml",
+        "# Echo service\nkind: tcp_echo\nport: 4000  # public port\ntracing: false\n# How frames are read\nread_mode:\n  type: lines  # one per line\n",
+        ConfigFormat::Yaml,
+    );
+    let migration = migrate_with(&yaml, &steps).unwrap();
+    assert_eq!(migration.from, 0);
+    let edits: Vec<String> = migration.edits.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        edits,
+        [
+            "renamed `port` to `listen_port`: ports are named after what they do",
+            "renamed `read_mode.type` to `kind`: read modes are picked by `kind`",
+            "removed `tracing`: tracing is always on",
+            "set `version` to 2: configs now record the schema version they follow",
+        ]
+    );
+    assert!(!migration.rewritten);
+    assert_eq!(
+        migration.text,
+        "# Echo service\nversion: 2\nkind: tcp_echo\nlisten_port: 4000  # public port\n# How frames are read\nread_mode:\n  kind: lines  # one per line\n"
+    );
+    assert_eq!(
+        migration.value,
+        serde_json::json!({
+            "version": 2,
+            "kind": "tcp_echo",
+            "listen_port": 4000,
+            "read_mode": { "kind": "lines" },
+        })
+    );
+
+    // A version 1 config only runs the second step.
+    let v1 = ConfigSource::new(
+        "echo.yaml",
+        "version: 1\nkind: tcp_echo\nport: 4000\nread_mode:\n  type: lines\n",
+        ConfigFormat::Yaml,
+    );
+    let migration = migrate_with(&v1, &steps).unwrap();
+    assert_eq!(migration.from, 1);
+    assert_eq!(migration.edits.len(), 3);
+    assert_eq!(
+        migration.text,
+        "version: 2\nkind: tcp_echo\nlisten_port: 4000\nread_mode:\n  kind: lines\n"
+    );
+
+    let toml = ConfigSource::new(
+        "echo.toml",
+        "# Echo service\nkind = \"tcp_echo\"\nport = 4000 # public port\ntracing = false\n\n[read_mode]\ntype = \"lines\"\n",
+        ConfigFormat::Toml,
+    );
+    let migration = migrate_with(&toml, &steps).unwrap();
+    assert!(!migration.rewritten);
+    assert_eq!(
+        migration.text,
+        "# Echo service\nversion = 2\nkind = \"tcp_echo\"\nlisten_port = 4000 # public port\n\n[read_mode]\nkind = \"lines\"\n"
+    );
+
+    // A flow mapping cannot be edited line by line and is written out again.
+    let flow = ConfigSource::new(
+        "echo.yaml",
+        "kind: tcp_echo\nport: 4000\nread_mode: { type: lines }\n",
+        ConfigFormat::Yaml,
+    );
+    let migration = migrate_with(&flow, &steps).unwrap();
+    assert!(migration.rewritten);
+    assert_eq!(
+        serde_yaml::from_str::<serde_json::Value>(&migration.text).unwrap(),
+        migration.value
+    );
+    assert_eq!(migration.value["read_mode"]["kind"], "lines");
+}
+
+#[test]
+fn test_length_field_widths() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-lp24");
+    let registry = Registry::builtin();
+
+    let config = |len_bytes: usize, extra: &str| {
+        yaml(format!(
+            "kind: tcp_worker\nproject_name: test-worker-lp24\nport: 5000\ntracing: false\nworkers: 2\nevent_buffer: 16\nread_mode:\n  type: length_prefixed\n  len_bytes: {}\n  big_endian: false\n{}",
+            len_bytes, extra
+        ))
+    };
+
+    // A 3-byte signed field is sign-extended into an i32.
+    let signed = config(3, "  signed: true\n");
+    let code = main_rs(&signed);
+    assert!(code.contains("fn decode_frame_len(header: [u8; 3])"));
+    assert!(code.contains("let mut buf = [fill; 4];"));
+    assert!(code.contains("i32::from_le_bytes(buf)"));
+
+    assert!(main_rs(&config(8, "")).contains("u64::from_le_bytes(header)"));
+    assert!(main_rs(&config(1, "")).contains("u8::from_le_bytes(header)"));
+
+    let err = render(&config(9, "")).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    assert_eq!(err.diagnostics[0].issue.path, "read_mode.len_bytes");
+    assert_eq!(
+        err.diagnostics[0].issue.suggestion.as_deref(),
+        Some("len_bytes: 8")
+    );
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-worker", &signed, &args).unwrap();
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_length_header_layout() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-echo-lp-header");
+    let registry = Registry::builtin();
+
+    // A 1-byte tag, then a 2-byte length that counts the whole frame; the
+    // tag is kept and only the length field is dropped.
+    let config = yaml(
+        "kind: tcp_echo\nproject_name: test-echo-lp-header\nport: 4000\ntracing: false\nread_mode:\n  type: length_prefixed\n  len_bytes: 2\n  big_endian: true\n  length_field_offset: 1\n  length_includes_header: true\n  length_adjustment: 1\n  num_skip: 0\n",
+    );
+    let code = main_rs(&config);
+    assert!(code.contains("const HEADER_LEN: usize = 3;"));
+    assert!(code.contains("const NUM_SKIP: usize = 0;"));
+    assert!(code.contains("field.copy_from_slice(&header[1..]);"));
+    assert!(code.contains("let total = len as i128 + (1);"));
+
+    let header = LengthHeader {
+        field: LengthField::new(2, false, true).unwrap(),
+        offset: 1,
+        adjustment: 0,
+        num_skip: None,
+        includes_header: false,
+    };
+    assert_eq!(header.header_len(), 3);
+    assert_eq!(header.num_skip(), 3);
+    assert_eq!(header.uncounted(), 3);
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-echo", &config, &args).unwrap();
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_codec_framing() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-codec");
+    let registry = Registry::builtin();
+
+    let config = yaml(
+        "kind: tcp_worker\nproject_name: test-worker-codec\nport: 5000\ntracing: true\nworkers: 2\nevent_buffer: 16\nframing: codec\nread_mode:\n  type: length_prefixed\n  len_bytes: 2\n  big_endian: true\n  max_len: 1024\n",
+    );
+    let rendered = render(&config).unwrap();
+    let code = &rendered.files["src/main.rs"];
+    assert!(code.contains("impl Decoder for FrameCodec"));
+    assert!(code.contains("Framed::new(socket, FrameCodec::default())"));
+    assert!(code.contains("fn decode_header(header: &[u8; HEADER_LEN])"));
+    assert!(!code.contains("read_exact"));
+    assert!(rendered.files["Cargo.toml"].contains("tokio-util"));
+    // Room is only reserved for frames within max_len.
+    assert!(code.contains("if frame_len > MAX_LEN {\n        return Err("));
+    assert!(code.contains("let total = frame_total(skip, frame_len)?;"));
+
+    // The read loop stays the default.
+    let looped = yaml(config.text.replace("framing: codec\n", ""));
+    let rendered = render(&looped).unwrap();
+    assert!(!rendered.files["src/main.rs"].contains("FrameCodec"));
+    assert!(!rendered.files["Cargo.toml"].contains("tokio-util"));
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-worker", &config, &args).unwrap();
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_multi_byte_delimiters() {
+    let delim = |yaml: &str| match serde_yaml::from_str::<YamlReadMode>(&format!(
+        "type: delimited\ndelim: {}\n",
+        yaml
+    )) {
+        Ok(YamlReadMode::Delimited { delim, .. }) => Ok(delim.0),
+        Ok(other) => panic!("unexpected read mode {:?}", other),
+        Err(err) => Err(err.to_string()),
+    };
+
+    assert_eq!(delim("10"), Ok(vec![10]));
+    assert_eq!(delim("'\\r\\n'"), Ok(b"\r\n".to_vec()));
+    assert_eq!(delim("\"\\r\\n\""), Ok(b"\r\n".to_vec()));
+    assert_eq!(delim("'END\\n'"), Ok(b"END\n".to_vec()));
+    assert_eq!(delim("'\\0\\0'"), Ok(vec![0, 0]));
+    assert_eq!(delim("'\\xff|'"), Ok(vec![0xff, b'|']));
+    assert_eq!(delim("'0x0d0a'"), Ok(vec![13, 10]));
+    assert_eq!(delim("[13, 10]"), Ok(vec![13, 10]));
+    assert!(delim("'\\q'").unwrap_err().contains("unknown escape"));
+    assert!(delim("'0x0d0'").unwrap_err().contains("not a hex string"));
+    assert!(delim("256").is_err());
+
+    let roundtrip = ByteString(b"0x\r\n\"".to_vec());
+    assert_eq!(ByteString::parse(&roundtrip.escaped()).unwrap(), roundtrip);
+    assert_eq!(roundtrip.rust_literal(), "b\"\\x30x\\r\\n\\\"\"");
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-crlf");
+    let registry = Registry::builtin();
+    let config = yaml(
+        "kind: tcp_worker\nproject_name: test-worker-crlf\nport: 5000\ntracing: true\nworkers: 2\nevent_buffer: 16\nread_mode:\n  type: delimited\n  delim: '\\r\\n'\n  strip_delim: true\n  max_len: 4096\n",
+    );
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-worker", &config, &args).unwrap();
+
+    let code = std::fs::read_to_string(project_dir.join("src/main.rs")).unwrap();
+    assert!(code.contains("const DELIMITER: &[u8] = b\"\\r\\n\";"));
+    assert!(code.contains("buf.truncate(buf.len() - DELIMITER.len());"));
+    // With max_len, no more than max_len and the delimiter is buffered.
+    assert!(code.contains("const MAX_KEPT: usize = 4096 + DELIMITER.len();"));
+    assert!(!code.contains("read_until"));
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+
+    // The codec drops the rest of an oversized frame instead of buffering it.
+    let codec = yaml(
+        config
+            .text
+            .replace("read_mode:", "framing: codec\nread_mode:"),
+    );
+    let code = main_rs(&codec);
+    assert!(code.contains("if src.len() >= MAX_LEN + DELIMITER.len() {"));
+    assert!(code.contains("None if self.discarding => {"));
+
+    let empty = yaml(config.text.replace("'\\r\\n'", "[]"));
+    let err = registry.run("tcp-worker", &empty, &args).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    assert_eq!(err.diagnostics[0].issue.path, "read_mode.delim");
+}
+
+#[test]
+fn test_serve_framing() {
+    // A delimiter split across reads still ends the frame.
+    for framing in ["loop", "codec"] {
+        let server = Server::start(
+            &format!("serve-delimited-{}", framing),
+            &format!(
+                "kind: tcp_echo\ntracing: false\nframing: {}\nread_mode:\n  type: delimited\n  delim: 'END'\n  max_len: 16\n",
+                framing
+            ),
+        );
+        let mut client = server.connect();
+        send_in_parts(&mut client, &[b"abcE", b"ND12", b"3EN", b"D"]);
+        assert_eq!(receive(&mut client, 12), b"abcEND123END");
+    }
+
+    // So does a varint split within the prefix.
+    let server = Server::start(
+        "serve-varint",
+        "kind: tcp_worker\ntracing: false\nworkers: 2\nevent_buffer: 16\nframing: codec\nresponse: same\nread_mode:\n  type: varint_prefixed\n",
+    );
+    let mut client = server.connect();
+    let payload = [b'x'; 200];
+    send_in_parts(
+        &mut client,
+        &[&[0xc8], &[0x01], &payload[..100], &payload[100..]],
+    );
+    assert_eq!(
+        receive(&mut client, 202),
+        [&[0xc8, 0x01][..], &payload].concat()
+    );
+
+    // A tag before a length that counts the whole frame; nothing is skipped.
+    let server = Server::start(
+        "serve-length-header",
+        "kind: tcp_echo\ntracing: false\nread_mode:\n  type: length_prefixed\n  len_bytes: 2\n  big_endian: true\n  length_field_offset: 1\n  length_includes_header: true\n  num_skip: 0\n",
+    );
+    let mut client = server.connect();
+    send_in_parts(&mut client, &[&[7, 0], &[6, b'a'], b"bc", &[7, 0, 3]]);
+    assert_eq!(
+        receive(&mut client, 9),
+        [7, 0, 6, b'a', b'b', b'c', 7, 0, 3]
+    );
+}
+
+#[test]
+fn test_on_oversize_policies() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-oversize");
+    let registry = Registry::builtin();
+
+    let config = |read_mode: &str| {
+        yaml(format!(
+            "kind: tcp_worker\nproject_name: test-worker-oversize\nport: 5000\ntracing: false\nworkers: 2\nevent_buffer: 16\nread_mode:\n{}",
+            read_mode
+        ))
+    };
+
+    let reply = config(
+        "  type: length_prefixed\n  len_bytes: 2\n  big_endian: true\n  max_len: 1024\n  on_oversize: reply_error\n  error_frame: 'ERR\\n'\n",
+    );
+    let code = main_rs(&reply);
+    assert!(code.contains("const ERROR_FRAME: &[u8] = b\"ERR\\n\";"));
+    assert!(code.contains("discard(&mut reader, NUM_SKIP + frame_len - HEADER_LEN).await?;"));
+    assert!(code.contains("exceeding max_len={}, replying with an error"));
+
+    // Without on_oversize, lines keep truncating and length_prefixed keeps closing.
+    let lines = main_rs(&config("  type: lines\n  max_line_len: 10\n"));
+    assert!(lines.contains("line from {} exceeded max_len = {}, truncating"));
+    let closing = main_rs(&config("  type: varint_prefixed\n  max_len: 10\n"));
+    assert!(closing.contains("exceeding max_len={}, closing"));
+
+    // Lengths sent by the client are capped at 8 MiB without max_len.
+    for mode in [
+        "  type: length_prefixed\n  len_bytes: 8\n  big_endian: true\n",
+        "  type: varint_prefixed\n  on_oversize: drop_frame\n",
+    ] {
+        let code = main_rs(&config(mode));
+        assert!(code.contains("if frame_len > 8388608 {"));
+    }
+
+    let invalid =
+        config("  type: delimited\n  delim: 10\n  on_oversize: drop_frame\n  error_frame: 'ERR'\n");
+    let err = render(&invalid).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    let paths: Vec<_> = err
+        .diagnostics
+        .iter()
+        .map(|d| d.issue.path.as_str())
+        .collect();
+    assert_eq!(paths, ["read_mode.on_oversize", "read_mode.error_frame"]);
+    assert_eq!(
+        err.diagnostics[0].issue.suggestion.as_deref(),
+        Some("max_len: 65535")
+    );
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-worker", &reply, &args).unwrap();
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_serve_oversize_policies() {
+    // The rest of a long frame is dropped, even when its delimiter is split;
+    // max_len counts the delimiter unless it is stripped.
+    let frames: [&[u8]; 4] = [b"abcEND", b"0123456789E", b"ND", b"okEND"];
+    let server = Server::start(
+        "serve-drop-frame",
+        "kind: tcp_echo\ntracing: false\nread_mode:\n  type: delimited\n  delim: 'END'\n  max_len: 8\n  on_oversize: drop_frame\n",
+    );
+    let mut client = server.connect();
+    send_in_parts(&mut client, &frames);
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(receive_until_closed(&mut client), b"abcENDokEND");
+
+    let server = Server::start(
+        "serve-reply-error",
+        "kind: tcp_worker\ntracing: false\nworkers: 2\nevent_buffer: 16\nframing: codec\nread_mode:\n  type: delimited\n  delim: 'END'\n  max_len: 8\n  on_oversize: reply_error\n  error_frame: 'frame too large\\n'\n",
+    );
+    let mut client = server.connect();
+    send_in_parts(&mut client, &frames);
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(
+        receive_until_closed(&mut client),
+        b"abcENDframe too large\nokEND"
+    );
+
+    // Lines are bytes, truncated at max_line_len by default.
+    let server = Server::start(
+        "serve-truncate",
+        "kind: tcp_echo\ntracing: false\nread_mode:\n  type: lines\n  max_line_len: 4\n",
+    );
+    let mut client = server.connect();
+    send_in_parts(&mut client, &[b"\xff\xfe\n", b"abcde", b"fgh\n", b"ok\n"]);
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(receive_until_closed(&mut client), b"\xff\xfe\nabcdok\n");
+
+    // Lengths over 8 MiB close the connection before anything is buffered.
+    let server = Server::start(
+        "serve-length-cap",
+        "kind: tcp_echo\ntracing: false\nread_mode:\n  type: length_prefixed\n  len_bytes: 4\n  big_endian: true\n",
+    );
+    let mut client = server.connect();
+    send_in_parts(&mut client, &[b"\0\0\0\x02hi", &[0xff; 4]]);
+    assert_eq!(receive_until_closed(&mut client), b"hi");
+    server.wait_for_log("exceeding max_len=8388608, closing");
+}
+
+#[test]
+fn test_lines_are_bounded_bytes() {
+    let render = |framing: &str, read_mode: &str| {
+        main_rs(&yaml(format!(
+            "kind: tcp_echo\nproject_name: test-echo-lines\nport: 5000\ntracing: false\nframing: {}\nread_mode:\n  type: lines\n{}",
+            framing, read_mode
+        )))
+    };
+
+    let bounded = render("loop", "  max_line_len: 100\n  on_oversize: close\n");
+    assert!(bounded.contains("const MAX_LINE_LEN: usize = 100;"));
+    assert!(bounded.contains("let n = read_line(&mut reader, &mut buf).await?;"));
+    assert!(bounded.contains("if done || total > MAX_LINE_LEN {"));
+    assert!(!bounded.contains("String::new()"));
+    assert!(!bounded.contains("from_utf8"));
+
+    let unbounded = render("loop", "  validate_utf8: true\n");
+    assert!(unbounded.contains("reader.read_until(b'\\n', &mut buf).await?"));
+    assert!(unbounded.contains("line from {} is not valid UTF-8, closing"));
+    assert!(!unbounded.contains("MAX_LINE_LEN"));
+
+    let codec = render("codec", "  max_line_len: 100\n");
+    assert!(codec.contains("discarding: bool,"));
+    assert!(codec.contains("if src.len() > MAX_LEN {"));
+    assert!(!codec.contains("from_utf8"));
+    let codec = render("codec", "  validate_utf8: true\n");
+    assert!(codec.contains("\"invalid UTF-8\""));
+}
+
+#[test]
+fn test_response_encoding() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-echo-response");
+    let registry = Registry::builtin();
+
+    let config = |framing: &str, response: &str| {
+        yaml(format!(
+            "kind: tcp_echo\nproject_name: test-echo-response\nport: 5000\ntracing: false\nframing: {}\nread_mode:\n  type: length_prefixed\n  len_bytes: 2\n  big_endian: true\n{}",
+            framing, response
+        ))
+    };
+
+    // Raw replies stay the default.
+    let raw = main_rs(&config("loop", ""));
+    assert!(raw.contains("writer.write_all(buf).await?;"));
+    assert!(!raw.contains("mod response"));
+
+    let same = config("loop", "response: same\n");
+    let code = main_rs(&same);
+    assert!(code.contains("write_reply(&mut writer, buf).await?;"));
+    assert!(code.contains("let reply = response::frame(frame)?;"));
+    assert!(code.contains("let field = (len as u16).to_be_bytes();"));
+    assert!(code.contains("if !(0..=65535).contains(&len) {"));
+
+    let varint = config("codec", "response:\n  type: varint_prefixed\n");
+    let code = main_rs(&varint);
+    assert!(code.contains("response::encode(frame.as_ref(), dst)"));
+    assert!(code.contains("pub fn encode(frame: &[u8], dst: &mut BytesMut)"));
+
+    let delimited = config("loop", "response:\n  type: delimited\n  delim: '\\r\\n'\n");
+    let code = main_rs(&delimited);
+    assert!(code.contains("const TERMINATOR: &[u8] = b\"\\r\\n\";"));
+
+    let invalid = config(
+        "loop",
+        "response:\n  type: length_prefixed\n  len_bytes: 2\n  big_endian: true\n  num_skip: 1\n  max_len: 10\n",
+    );
+    let err = render(&invalid).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    let issues: Vec<_> = err
+        .diagnostics
+        .iter()
+        .map(|d| (d.issue.path.as_str(), d.issue.suggestion.as_deref()))
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            ("response.max_len", None),
+            ("response", Some("num_skip: 2"))
+        ]
+    );
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-echo", &same, &args).unwrap();
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_timeouts() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-timeouts");
+    let registry = Registry::builtin();
+
+    let config = |framing: &str, timeouts: &str| {
+        yaml(format!(
+            "kind: tcp_worker\nproject_name: test-worker-timeouts\nport: 5000\ntracing: true\nworkers: 2\nevent_buffer: 16\nframing: {}\nread_mode:\n  type: fixed_size\n  frame_size: 8\ntimeouts:\n{}",
+            framing, timeouts
+        ))
+    };
+
+    let all = config("loop", "  idle: 30s\n  read_frame: 1500ms\n  write: 5\n");
+    let code = main_rs(&all);
+    assert!(code
+        .contains("const IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);"));
+    assert!(code.contains(
+        "const READ_FRAME_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(1500);"
+    ));
+    assert!(code
+        .contains("const WRITE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);"));
+    assert!(code.contains("let next = Timeout::Idle.within(reader.peek(&mut first));"));
+    assert!(code.contains("Timeout::ReadFrame.by(deadline, reader.read_exact(&mut buf)).await?"));
+    assert!(code.contains("write_reply(&mut writer, &buf).await?;"));
+    assert!(code.contains("closing connection from {}: {}"));
+
+    let codec = config("codec", "  idle: 30s\n");
+    let code = main_rs(&codec);
+    assert!(code.contains("let started = Timeout::Idle.within(started);"));
+    assert!(code.contains("Ok(framed.next().await)"));
+    assert!(!code.contains("READ_FRAME_TIMEOUT"));
+
+    let zero = config("loop", "  idle: 0\n");
+    let err = render(&zero).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    let issues: Vec<_> = err
+        .diagnostics
+        .iter()
+        .map(|d| (d.issue.path.as_str(), d.issue.suggestion.as_deref()))
+        .collect();
+    assert_eq!(issues, [("timeouts.idle", Some("idle: 30s"))]);
+
+    let err = render(&config("loop", "  write: 5x\n")).unwrap_err();
+    assert!(format!("{:#}", err).contains("unknown unit `x` in duration `5x`"));
+    assert_eq!(
+        ConfigDuration::parse("2m").unwrap(),
+        ConfigDuration(Duration::from_secs(120))
+    );
+    assert_eq!(
+        ConfigDuration::parse("1500ms").unwrap().to_string(),
+        "1500ms"
+    );
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-worker", &all, &args).unwrap();
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_serve_timeouts() {
+    for framing in ["loop", "codec"] {
+        let server = Server::start(
+            &format!("serve-timeouts-{}", framing),
+            &format!(
+                "kind: tcp_worker\ntracing: false\nworkers: 2\nevent_buffer: 16\nframing: {}\nread_mode:\n  type: fixed_size\n  frame_size: 4\ntimeouts:\n  idle: 300ms\n  read_frame: 300ms\n",
+                framing
+            ),
+        );
+
+        // A client that goes quiet between frames is closed once idle.
+        let mut idle = server.connect();
+        idle.write_all(b"abcd").unwrap();
+        assert_eq!(receive(&mut idle, 4), b"abcd");
+        let replied = Instant::now();
+        assert_eq!(receive_until_closed(&mut idle), b"");
+        assert!(replied.elapsed() >= Duration::from_millis(250));
+        server.wait_for_log("idle for 300ms");
+
+        // So is one that stops in the middle of a frame.
+        let mut stalled = server.connect();
+        stalled.write_all(b"ab").unwrap();
+        assert_eq!(receive_until_closed(&mut stalled), b"");
+        server.wait_for_log("frame not complete after 300ms");
+    }
+}
+
+#[test]
+fn test_graceful_shutdown() {
+    let render = |source: &str| render(&yaml(source));
+
+    let echo = render(
+        "kind: tcp_echo\nproject_name: test-echo-shutdown\nport: 5000\ntracing: false\nshutdown_grace: 30s\nread_mode:\n  type: lines\n",
+    )
+    .unwrap();
+    let code = &echo.files["src/main.rs"];
+    assert!(code.contains(
+        "const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(30);"
+    ));
+    assert!(code.contains("async fn shutdown_signal() -> &'static str {"));
+    assert!(code.contains("signal = &mut shutdown => break signal,"));
+    assert!(code.contains("connections.shutdown().await;"));
+    // Open connections close between frames once the signal is received.
+    assert!(code.contains("stop.send_replace(true);"));
+    assert!(code.contains("handle_client(socket, peer_addr, stopping.clone());"));
+    assert!(code.contains("let Some(next) = unless_stopping(&mut stopping, next).await else {"));
+
+    // Without `shutdown_grace`, connections get 10 seconds.
+    let worker = render(
+        "kind: tcp_worker\nproject_name: test-worker-shutdown\nport: 5000\ntracing: true\nworkers: 2\nevent_buffer: 16\nread_mode:\n  type: lines\n",
+    )
+    .unwrap();
+    let code = &worker.files["src/main.rs"];
+    assert!(code.contains("std::time::Duration::from_secs(10);"));
+    assert!(code.contains("drop(tx);\n    while pool.join_next().await.is_some() {}"));
+    assert!(code.contains("handle_client(socket, peer_addr, stopping.clone(), tx.clone());"));
+
+    let axum = render(
+        "kind: http_axum\nproject_name: test-axum-shutdown\nport: 3000\ntracing: true\nshutdown_grace: 2500ms\nroutes:\n  - { path: /, method: GET, handler: root, response: OK }\n",
+    )
+    .unwrap();
+    let code = &axum.files["src/main.rs"];
+    assert!(code.contains("std::time::Duration::from_millis(2500);"));
+    assert!(code.contains(".with_graceful_shutdown(async move {"));
+
+    let err = render(
+        "kind: tcp_echo\nproject_name: test-echo-shutdown\nport: 5000\ntracing: false\nshutdown_grace: soon\nread_mode:\n  type: lines\n",
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("invalid duration `soon`"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve_graceful_shutdown() {
+    for framing in ["loop", "codec"] {
+        let mut server = Server::start(
+            &format!("serve-shutdown-{}", framing),
+            &format!(
+                "kind: tcp_echo\ntracing: false\nshutdown_grace: 1s\nframing: {}\nread_mode:\n  type: lines\n",
+                framing
+            ),
+        );
+        let mut idle = server.connect();
+        let mut busy = server.connect();
+        let mut stuck = server.connect();
+        for client in [&mut idle, &mut busy, &mut stuck] {
+            client.write_all(b"hi\n").unwrap();
+            assert_eq!(receive(client, 3), b"hi\n");
+        }
+        busy.write_all(b"half").unwrap();
+        stuck.write_all(b"half").unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        // Idle connections close right away, and new ones are refused.
+        server.terminate();
+        assert_eq!(receive_until_closed(&mut idle), b"");
+        server.wait_for_log("received SIGTERM, waiting up to 1s for 3 open connections");
+        assert!(TcpStream::connect(("127.0.0.1", server.port)).is_err());
+
+        // A connection in the middle of a frame closes after replying to it.
+        busy.write_all(b" done\n").unwrap();
+        assert_eq!(receive_until_closed(&mut busy), b"half done\n");
+
+        // The rest are closed once the grace period is over.
+        server.wait_for_log("closing 1 connections still open");
+        assert_eq!(receive_until_closed(&mut stuck), b"");
+        server.wait_for_exit();
+        server.wait_for_log("server stopped");
+    }
+}
+
+#[test]
+fn test_connection_limits() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let project_dir = temp_dir.path().join("test-worker-limits");
+    let registry = Registry::builtin();
+
+    let config = |framing: &str, limits: &str| {
+        yaml(format!(
+            "kind: tcp_worker\nproject_name: test-worker-limits\nport: 5000\ntracing: true\nworkers: 2\nevent_buffer: 16\nframing: {}\nresponse: same\nread_mode:\n  type: varint_prefixed\n{}",
+            framing, limits
+        ))
+    };
+
+    let reject = config(
+        "codec",
+        "max_connections: 512\nmax_connections_per_ip: 8\ngoodbye_frame: 'busy\\n'\n",
+    );
+    let code = main_rs(&reject);
+    assert!(code.contains("const MAX_CONNECTIONS: usize = 512;"));
+    assert!(code.contains("const MAX_CONNECTIONS_PER_IP: usize = 8;"));
+    assert!(code.contains("const GOODBYE_FRAME: &[u8] = b\"busy\\n\";"));
+    assert!(code.contains("let Ok(permit) = Arc::clone(&open).try_acquire_owned() else {"));
+    assert!(code.contains("per_ip.semaphore(peer_addr.ip()).try_acquire_owned()"));
+    assert!(code.contains("tokio::spawn(say_goodbye(socket));"));
+    assert!(code.contains("FrameCodec::default().encode(GOODBYE_FRAME, &mut goodbye)"));
+
+    let wait = config("loop", "max_connections: 512\non_limit: wait\n");
+    let code = main_rs(&wait);
+    assert!(code.contains("permit = Arc::clone(&open).acquire_owned() => permit?,"));
+    assert!(!code.contains("MAX_CONNECTIONS_PER_IP"));
+    assert!(!code.contains("say_goodbye"));
+
+    // No limit, no semaphores.
+    let code = main_rs(&config("loop", ""));
+    assert!(!code.contains("Semaphore"));
+
+    let invalid = config(
+        "loop",
+        "max_connections: 0\non_limit: wait\ngoodbye_frame: 'busy\\n'\n",
+    );
+    let err = render(&invalid).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    let issues: Vec<_> = err
+        .diagnostics
+        .iter()
+        .map(|d| (d.issue.path.as_str(), d.issue.suggestion.as_deref()))
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            ("max_connections", Some("max_connections: 1000")),
+            ("goodbye_frame", Some("on_limit: reject"))
+        ]
+    );
+
+    let unlimited = config("loop", "goodbye_frame: 'busy\\n'\n");
+    let err = render(&unlimited).unwrap_err();
+    let err = err.downcast_ref::<ValidationError>().unwrap();
+    assert_eq!(err.diagnostics[0].issue.path, "goodbye_frame");
+
+    let args = GenerateArgs {
+        out_dir: Some(project_dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    registry.run("tcp-worker", &reject, &args).unwrap();
+    cargo_check(&project_dir).expect("Generated project failed to compile");
+}
+
+#[test]
+fn test_serve_connection_limits() {
+    // Connections over the limit get the goodbye frame and are closed.
+    let server = Server::start(
+        "serve-limit-reject",
+        "kind: tcp_echo\ntracing: false\nmax_connections: 2\ngoodbye_frame: 'busy\\n'\nread_mode:\n  type: lines\n",
+    );
+    let mut first = server.connect();
+    let mut second = server.connect();
+    for client in [&mut first, &mut second] {
+        client.write_all(b"hi\n").unwrap();
+        assert_eq!(receive(client, 3), b"hi\n");
+    }
+    let mut rejected = server.connect();
+    assert_eq!(receive_until_closed(&mut rejected), b"busy\n");
+    server.wait_for_log("2 connections open");
+
+    // A closed connection makes room for a new one.
+    drop(first);
+    server.wait_for_log("disconnected");
+    thread::sleep(Duration::from_millis(100));
+    let mut third = server.connect();
+    third.write_all(b"hi\n").unwrap();
+    assert_eq!(receive(&mut third, 3), b"hi\n");
+
+    // With on_limit: wait, a connection over the limit is served once
+    // another closes.
+    let server = Server::start(
+        "serve-limit-wait",
+        "kind: tcp_worker\ntracing: false\nworkers: 2\nevent_buffer: 16\nframing: codec\nresponse: same\nmax_connections_per_ip: 1\non_limit: wait\nread_mode:\n  type: varint_prefixed\n",
+    );
+    let mut first = server.connect();
+    first.write_all(b"\x01a").unwrap();
+    assert_eq!(receive(&mut first, 2), b"\x01a");
+    let mut waiting = server.connect();
+    waiting.write_all(b"\x01b").unwrap();
+    server.wait_for_log("delaying connection from");
+    waiting
+        .set_read_timeout(Some(Duration::from_millis(300)))
+        .unwrap();
+    assert!(waiting.read(&mut [0; 2]).is_err());
+    drop(first);
+    waiting
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    assert_eq!(receive(&mut waiting, 2), b"\x01b");
+}